@@ -0,0 +1,229 @@
+//! Files a deck refers to (images), loaded in the background.
+//!
+//! Each deck has an `AssetStore`. Slides ask for their images while the deck
+//! is split; a small pool of worker threads fetches them, and the view looks
+//! up whatever has arrived when it draws. The store is kept across reloads so
+//! that only images whose file changed are fetched again.
+
+use error::Error;
+use get::{get_vec, local_file};
+use image::Image;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+use std::time::SystemTime;
+
+/// Number of threads fetching assets.
+const WORKERS: usize = 4;
+
+/// The state of an asset.
+#[derive(Clone)]
+pub enum Asset {
+    /// Being fetched.
+    Pending,
+    Loaded(Arc<Image>),
+    Failed(Arc<Error>),
+}
+
+struct Entry {
+    /// What `get_vec` loads the asset from.
+    location: String,
+    state: Asset,
+
+    /// Modification time of a local file when it was read.
+    modified: Option<SystemTime>,
+
+    /// Whether the deck asked for it since the last `sweep`.
+    used: bool,
+}
+
+/// Name (as written in the deck) and location of an asset to fetch.
+type Job = (String, String);
+
+type Entries = Arc<Mutex<HashMap<String, Entry>>>;
+
+/// Called with the name of an asset when fetching it in the background is
+/// done.
+type Listener = Arc<Mutex<Option<Box<dyn Fn(&str) + Send>>>>;
+
+/// Assets of a deck by the name they're referred to with. Clones share the
+/// same assets.
+#[derive(Clone, Default)]
+pub struct AssetStore {
+    entries: Entries,
+
+    /// Queue of the worker pool, started with the first request.
+    jobs: Arc<Mutex<Option<Sender<Job>>>>,
+
+    listener: Listener,
+}
+
+impl AssetStore {
+    pub fn new() -> AssetStore {
+        AssetStore::default()
+    }
+
+    /// The asset `name`, if it was asked for.
+    pub fn get(&self, name: &str) -> Option<Asset> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(name).map(|entry| entry.state.clone())
+    }
+
+    /// Call `f` with the name of each asset fetched in the background once
+    /// it's loaded or failed, e.g. to show it.
+    pub fn on_done<F>(&self, f: F)
+    where
+        F: Fn(&str) + Send + 'static,
+    {
+        *self.listener.lock().unwrap() = Some(Box::new(f));
+    }
+
+    /// Fetch `name` from `location` in the background, unless it's already
+    /// loaded or being fetched.
+    pub fn request(&self, name: &str, location: &str) {
+        if !self.mark(name, location) {
+            return;
+        }
+
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.is_none() {
+            *jobs = Some(self.start());
+        }
+        let job = (String::from(name), String::from(location));
+        if jobs.as_ref().unwrap().send(job).is_err() {
+            error!("asset workers are gone, can't load {}", name);
+        }
+    }
+
+    /// Fetch `name` from `location` right away, unless it's already loaded.
+    pub fn load(&self, name: &str, location: &str) {
+        if !self.mark(name, location) {
+            if let Some(Asset::Loaded(_)) = self.get(name) {
+                return;
+            }
+        }
+        finish(&self.entries, name, location, fetch(location));
+    }
+
+    /// Note that `name` is used, adding it as pending if it's not known (or
+    /// failed before). Returns whether it needs to be fetched.
+    fn mark(&self, name: &str, location: &str) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(name) {
+            entry.used = true;
+            match entry.state {
+                Asset::Failed(_) => {}
+                _ if entry.location == location => return false,
+                _ => {}
+            }
+        }
+
+        let entry = Entry {
+            location: String::from(location),
+            state: Asset::Pending,
+            modified: None,
+            used: true,
+        };
+        entries.insert(String::from(name), entry);
+        true
+    }
+
+    /// Start the worker threads, returning the queue to send them jobs.
+    fn start(&self) -> Sender<Job> {
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..WORKERS {
+            let entries = self.entries.clone();
+            let listener = self.listener.clone();
+            let receiver = receiver.clone();
+            thread::spawn(move || work(&entries, &listener, &receiver));
+        }
+        sender
+    }
+
+    /// Forget the assets read from files that changed since, so that they're
+    /// fetched again when asked for.
+    pub fn invalidate(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|name, entry| {
+            let changed = entry.modified.is_some() && modified(&entry.location) != entry.modified;
+            if changed {
+                debug!("{} changed", name);
+            }
+            !changed
+        });
+    }
+
+    /// The local files assets are read from.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .values()
+            .filter_map(|entry| local_file(&entry.location))
+            .collect()
+    }
+
+    /// Drop the assets that weren't asked for since the last sweep.
+    pub fn sweep(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.used);
+        for entry in entries.values_mut() {
+            entry.used = false;
+        }
+    }
+}
+
+impl fmt::Debug for AssetStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entries = self.entries.lock().unwrap();
+        f.debug_list().entries(entries.keys()).finish()
+    }
+}
+
+/// Take jobs from `receiver` until the store is dropped.
+fn work(entries: &Entries, listener: &Listener, receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = receiver.lock().unwrap().recv();
+        let (name, location) = match job {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        let result = fetch(&location);
+        finish(entries, &name, &location, result);
+        if let Some(ref f) = *listener.lock().unwrap() {
+            f(&name);
+        }
+    }
+}
+
+/// Read and decode the asset at `location`, with the modification time of
+/// the file.
+fn fetch(location: &str) -> (Asset, Option<SystemTime>) {
+    let modified = modified(location);
+    let state = match get_vec(location) {
+        Ok(data) => Asset::Loaded(Arc::new(Image::new(data))),
+        Err(e) => Asset::Failed(Arc::new(e)),
+    };
+    (state, modified)
+}
+
+/// Store the result of fetching `name`, unless it was invalidated meanwhile.
+fn finish(entries: &Entries, name: &str, location: &str, result: (Asset, Option<SystemTime>)) {
+    let mut entries = entries.lock().unwrap();
+    if let Some(entry) = entries.get_mut(name) {
+        if entry.location == location {
+            debug!("loaded {} from {}", name, location);
+            entry.state = result.0;
+            entry.modified = result.1;
+        }
+    }
+}
+
+fn modified(location: &str) -> Option<SystemTime> {
+    let file = local_file(location)?;
+    fs::metadata(file).and_then(|m| m.modified()).ok()
+}
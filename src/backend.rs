@@ -0,0 +1,279 @@
+//! Where a rendered `Buffer` ends up: the terminal, or memory for tests.
+
+use buffer::{Buffer, Color, Style};
+use image;
+use std::cell::RefCell;
+use std::io::{Result, Stdout, Write, stdout};
+use std::rc::Rc;
+use termion::{self, color, cursor, screen, style};
+use unicode_width::UnicodeWidthStr;
+
+pub trait Backend {
+    /// Size of the screen in columns and rows.
+    fn size(&self) -> Result<(u16, u16)>;
+
+    /// Show `buffer` on the screen.
+    fn draw(&mut self, buffer: &Buffer) -> Result<()>;
+
+    fn hide_cursor(&mut self) -> Result<()>;
+
+    fn show_cursor(&mut self) -> Result<()>;
+
+    /// Clear the screen and put the cursor at the top left.
+    fn reset(&mut self) -> Result<()>;
+
+    /// Forget what's on the screen, so that the next `draw` redraws
+    /// everything.
+    fn invalidate(&mut self) {}
+
+    /// Take over the screen before the first `draw`.
+    fn enter(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Give the screen back when done.
+    fn leave(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Draws to stdout with termion. Only the cells that changed since the
+/// previous frame are written.
+pub struct Termion {
+    stdout: Stdout,
+    previous: Option<Buffer>,
+    /// Draw on the alternate screen, leaving the terminal's contents and
+    /// scrollback alone.
+    alternate_screen: bool,
+}
+
+impl Termion {
+    pub fn new(alternate_screen: bool) -> Termion {
+        Termion {
+            stdout: stdout(),
+            previous: None,
+            alternate_screen,
+        }
+    }
+}
+
+impl Backend for Termion {
+    fn size(&self) -> Result<(u16, u16)> {
+        termion::terminal_size()
+    }
+
+    fn draw(&mut self, buffer: &Buffer) -> Result<()> {
+        // Images can't be compared cell by cell, redraw everything if they
+        // changed.
+        let previous = match self.previous {
+            Some(ref previous) if previous.width() == buffer.width() &&
+                                  previous.height() == buffer.height() &&
+                                  previous.raw() == buffer.raw() => Some(previous),
+            _ => None,
+        };
+
+        let mut out = self.stdout.lock();
+        write!(out, "{}", style::Reset)?;
+        if previous.is_none() {
+            write!(out, "{}", termion::clear::All)?;
+            image::clear_images(&mut out)?;
+        }
+
+        let mut current = Style::default();
+        for y in 0..buffer.height() {
+            // Column the terminal cursor is at, if it's on this row
+            let mut at = None;
+            for x in 0..buffer.width() {
+                let cell = match buffer.get(x, y) {
+                    Some(cell) => cell,
+                    None => continue,
+                };
+                let unchanged = match previous {
+                    Some(previous) => previous.get(x, y) == Some(cell),
+                    None => cell.is_blank(),
+                };
+                // The right half of a wide character is drawn with the left.
+                if unchanged || cell.symbol.is_empty() {
+                    continue;
+                }
+
+                if at != Some(x) {
+                    write!(out, "{}", cursor::Goto(x + 1, y + 1))?;
+                }
+                if cell.style != current {
+                    write_style(&mut out, &cell.style)?;
+                    current = cell.style;
+                }
+                write!(out, "{}", cell.symbol)?;
+                at = Some(x + cell.symbol.width() as u16);
+            }
+        }
+        write!(out, "{}", style::Reset)?;
+
+        if previous.is_none() {
+            for &(x, y, ref data) in buffer.raw() {
+                write!(out, "{}", cursor::Goto(x + 1, y + 1))?;
+                out.write_all(data)?;
+            }
+        }
+        out.flush()?;
+
+        self.previous = Some(buffer.clone());
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> Result<()> {
+        write!(self.stdout, "{}", cursor::Hide)?;
+        self.stdout.flush()
+    }
+
+    fn show_cursor(&mut self) -> Result<()> {
+        write!(self.stdout, "{}", cursor::Show)?;
+        self.stdout.flush()
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        write!(
+            self.stdout,
+            "{}{}{}",
+            style::Reset,
+            termion::clear::All,
+            cursor::Goto(1, 1)
+        )?;
+        self.previous = None;
+        self.stdout.flush()
+    }
+
+    fn invalidate(&mut self) {
+        self.previous = None;
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        if self.alternate_screen {
+            write!(self.stdout, "{}", screen::ToAlternateScreen)?;
+            self.stdout.flush()?;
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self) -> Result<()> {
+        if self.alternate_screen {
+            write!(self.stdout, "{}", screen::ToMainScreen)?;
+            self.stdout.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn write_style<W: Write>(out: &mut W, s: &Style) -> Result<()> {
+    write!(out, "{}", style::Reset)?;
+    write_color(out, s.fg, false)?;
+    write_color(out, s.bg, true)?;
+    if s.bold {
+        write!(out, "{}", style::Bold)?;
+    }
+    if s.italic {
+        write!(out, "{}", style::Italic)?;
+    }
+    if s.underline {
+        write!(out, "{}", style::Underline)?;
+    }
+    if s.invert {
+        write!(out, "{}", style::Invert)?;
+    }
+    Ok(())
+}
+
+fn write_color<W: Write>(out: &mut W, c: Color, bg: bool) -> Result<()> {
+    macro_rules! set {
+        ($c:expr) => {
+            if bg {
+                write!(out, "{}", color::Bg($c))
+            } else {
+                write!(out, "{}", color::Fg($c))
+            }
+        }
+    }
+    match c {
+        Color::Reset => Ok(()),
+        Color::Black => set!(color::Black),
+        Color::LightBlack => set!(color::LightBlack),
+        Color::LightRed => set!(color::LightRed),
+        Color::LightCyan => set!(color::LightCyan),
+        Color::LightWhite => set!(color::LightWhite),
+        Color::Ansi(value) => set!(color::AnsiValue(value)),
+        Color::Rgb(r, g, b) => set!(color::Rgb(r, g, b)),
+    }
+}
+
+/// Keeps the last drawn buffer in memory, so that rendering can be checked
+/// without a terminal. Clones share the same screen, so a test can keep one
+/// and hand the other to a `View`.
+#[derive(Clone)]
+pub struct Headless {
+    width: u16,
+    height: u16,
+    screen: Rc<RefCell<Screen>>,
+}
+
+struct Screen {
+    buffer: Buffer,
+    cursor_visible: bool,
+}
+
+impl Headless {
+    pub fn new(width: u16, height: u16) -> Headless {
+        let screen = Screen {
+            buffer: Buffer::new(width, height),
+            cursor_visible: true,
+        };
+        Headless {
+            width,
+            height,
+            screen: Rc::new(RefCell::new(screen)),
+        }
+    }
+
+    /// The last drawn buffer.
+    pub fn buffer(&self) -> Buffer {
+        self.screen.borrow().buffer.clone()
+    }
+
+    /// The text of each row of the last drawn buffer.
+    pub fn lines(&self) -> Vec<String> {
+        let screen = self.screen.borrow();
+        (0..screen.buffer.height())
+            .map(|y| screen.buffer.line(y))
+            .collect()
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.screen.borrow().cursor_visible
+    }
+}
+
+impl Backend for Headless {
+    fn size(&self) -> Result<(u16, u16)> {
+        Ok((self.width, self.height))
+    }
+
+    fn draw(&mut self, buffer: &Buffer) -> Result<()> {
+        self.screen.borrow_mut().buffer = buffer.clone();
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> Result<()> {
+        self.screen.borrow_mut().cursor_visible = false;
+        Ok(())
+    }
+
+    fn show_cursor(&mut self) -> Result<()> {
+        self.screen.borrow_mut().cursor_visible = true;
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.screen.borrow_mut().buffer.clear();
+        Ok(())
+    }
+}
@@ -1,27 +1,42 @@
 extern crate env_logger;
 extern crate mdp;
 extern crate structopt;
-#[macro_use]
-extern crate structopt_derive;
 
-use std::io::Result;
+use std::process;
 use std::string::String;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "mdp", about = "A markdown presentation tool in Rust.")]
 struct Opt {
-    /// Path to the markdown file.
-    #[structopt(help = "Markdown file")]
+    /// Path to the markdown file, `-` for stdin.
+    #[structopt(help = "Markdown file or URL, - for stdin")]
     file: String,
+
+    /// Only show what's cached from earlier runs when loading from URLs.
+    #[structopt(long = "offline", help = "Don't fetch URLs, use the cache")]
+    offline: bool,
+
+    /// Reload when the deck or its images change.
+    #[structopt(short = "w", long = "watch", help = "Reload when the deck or its images change")]
+    watch: bool,
+
+    /// Draw over the terminal's contents instead of on the alternate screen,
+    /// e.g. for recording.
+    #[structopt(long = "no-alternate-screen", help = "Don't use the alternate screen")]
+    no_alternate_screen: bool,
 }
 
 fn main() {
-    env_logger::init().unwrap();
+    env_logger::init();
     let opt = Opt::from_args();
-    run(opt).unwrap();
+    if let Err(e) = run(opt) {
+        eprintln!("mdp: {}", e);
+        process::exit(1);
+    }
 }
 
-fn run(opt: Opt) -> Result<()> {
-    mdp::play(&opt.file)
+fn run(opt: Opt) -> Result<(), mdp::Error> {
+    mdp::set_offline(opt.offline);
+    mdp::play(&opt.file, opt.watch, !opt.no_alternate_screen)
 }
@@ -0,0 +1,184 @@
+//! A screen sized grid of styled cells that a slide is rendered into before
+//! it's shown by a `Backend`.
+
+use unicode_width::UnicodeWidthChar;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    #[default]
+    Reset,
+    Black,
+    LightBlack,
+    LightRed,
+    LightCyan,
+    LightWhite,
+    /// One of the 256 colors.
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub invert: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    /// What's shown in this cell: a character with its combining marks. The
+    /// cell right of a wide character is empty.
+    pub symbol: String,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Cell {
+        Cell {
+            symbol: String::from(" "),
+            style: Style::default(),
+        }
+    }
+}
+
+impl Cell {
+    pub fn is_blank(&self) -> bool {
+        self.symbol == " " && self.style == Style::default()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Buffer {
+    width: u16,
+    height: u16,
+    cells: Vec<Cell>,
+
+    /// Data passed through to the terminal as is (e.g. inline images), drawn
+    /// at a position after the cells.
+    raw: Vec<(u16, u16, Vec<u8>)>,
+}
+
+impl Buffer {
+    pub fn new(width: u16, height: u16) -> Buffer {
+        Buffer {
+            width,
+            height,
+            cells: vec![Cell::default(); width as usize * height as usize],
+            raw: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn resize(&mut self, width: u16, height: u16) {
+        *self = Buffer::new(width, height);
+    }
+
+    pub fn clear(&mut self) {
+        for cell in &mut self.cells {
+            *cell = Cell::default();
+        }
+        self.raw.clear();
+    }
+
+    fn index(&self, x: u16, y: u16) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y as usize * self.width as usize + x as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, x: u16, y: u16) -> Option<&Cell> {
+        self.index(x, y).map(|i| &self.cells[i])
+    }
+
+    /// Put `c` at `(x, y)` and return the number of columns it takes. Wide
+    /// characters that don't fit are dropped, combining marks are added to the
+    /// cell on the left.
+    pub fn set(&mut self, x: u16, y: u16, c: char, style: Style) -> u16 {
+        let width = c.width().unwrap_or(0) as u16;
+        if width == 0 {
+            if x > 0 {
+                if let Some(i) = self.index(x - 1, y) {
+                    self.cells[i].symbol.push(c);
+                }
+            }
+            return 0;
+        }
+
+        if self.index(x + width - 1, y).is_none() {
+            return width;
+        }
+        if let Some(i) = self.index(x, y) {
+            self.cells[i] = Cell {
+                symbol: c.to_string(),
+                style,
+            };
+        }
+        for dx in 1..width {
+            if let Some(i) = self.index(x + dx, y) {
+                self.cells[i] = Cell {
+                    symbol: String::new(),
+                    style,
+                };
+            }
+        }
+        width
+    }
+
+    /// Move the cells `from..to` of row `y` right by `n` columns, leaving
+    /// blanks behind. Cells moved past the right edge are lost.
+    pub fn shift_right(&mut self, y: u16, from: u16, to: u16, n: u16) {
+        if n == 0 {
+            return;
+        }
+        for x in (from..to).rev() {
+            let cell = match self.index(x, y) {
+                Some(i) => ::std::mem::take(&mut self.cells[i]),
+                None => continue,
+            };
+            if let Some(i) = self.index(x + n, y) {
+                self.cells[i] = cell;
+            }
+        }
+        for raw in &mut self.raw {
+            if raw.1 == y && raw.0 >= from && raw.0 < to {
+                raw.0 += n;
+            }
+        }
+    }
+
+    pub fn put_raw(&mut self, x: u16, y: u16, data: Vec<u8>) {
+        self.raw.push((x, y, data));
+    }
+
+    pub fn raw(&self) -> &[(u16, u16, Vec<u8>)] {
+        &self.raw
+    }
+
+    /// The cells of row `y`, without trailing blanks.
+    pub fn row(&self, y: u16) -> &[Cell] {
+        let start = y as usize * self.width as usize;
+        let row = &self.cells[start..start + self.width as usize];
+        let len = row.iter().rposition(|cell| !cell.is_blank()).map_or(
+            0,
+            |i| i + 1,
+        );
+        &row[..len]
+    }
+
+    /// The text of row `y`, without styles.
+    pub fn line(&self, y: u16) -> String {
+        self.row(y).iter().map(|cell| &cell.symbol[..]).collect()
+    }
+}
@@ -0,0 +1,138 @@
+//! On-disk cache of files fetched from URLs
+//!
+//! Each URL is stored in `$XDG_CACHE_HOME/mdp` (or `~/.cache/mdp`) as two
+//! files named after a hash of the URL: the body, and a `.headers` file with
+//! the URL and the validators the server sent:
+//!
+//! ```ignore
+//! url: https://example.com/deck.md
+//! etag: "5a1b-56e4"
+//! last-modified: Tue, 06 Feb 2018 10:00:00 GMT
+//! ```
+
+use reqwest::Url;
+use std::env::var_os;
+use std::fs::{self, File};
+use std::io::{Read, Result, Write};
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Clone)]
+pub struct Entry {
+    pub body: Vec<u8>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+fn dir() -> Option<PathBuf> {
+    let cache = match var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(var_os("HOME")?).join(".cache"),
+    };
+    Some(cache.join("mdp"))
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` stays the same across builds.
+fn key(url: &Url) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in url.as_str().bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// The cached copy of `url`, if there is one.
+pub fn load(url: &Url) -> Option<Entry> {
+    let path = dir()?.join(key(url));
+
+    let mut headers = String::new();
+    File::open(path.with_extension("headers"))
+        .and_then(|mut f| f.read_to_string(&mut headers))
+        .ok()?;
+    let mut entry = Entry::default();
+    let mut cached_url = None;
+    for line in headers.lines() {
+        let mut kv = line.splitn(2, ": ");
+        match (kv.next(), kv.next()) {
+            (Some("url"), Some(value)) => cached_url = Some(value),
+            (Some("etag"), Some(value)) => entry.etag = Some(String::from(value)),
+            (Some("last-modified"), Some(value)) => {
+                entry.last_modified = Some(String::from(value))
+            }
+            _ => {}
+        }
+    }
+    // Different URL with the same hash
+    if cached_url != Some(url.as_str()) {
+        return None;
+    }
+
+    File::open(&path)
+        .and_then(|mut f| f.read_to_end(&mut entry.body))
+        .ok()?;
+    Some(entry)
+}
+
+/// Keep `entry` as the copy of `url`.
+pub fn store(url: &Url, entry: &Entry) -> Result<()> {
+    let dir = match dir() {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(key(url));
+
+    // Write to a temporary file first so that an interrupted write doesn't
+    // leave a truncated body behind.
+    let tmp = path.with_extension("tmp");
+    File::create(&tmp).and_then(|mut f| f.write_all(&entry.body))?;
+    fs::rename(&tmp, &path)?;
+
+    let mut headers = File::create(path.with_extension("headers"))?;
+    writeln!(headers, "url: {}", url)?;
+    if let Some(ref etag) = entry.etag {
+        writeln!(headers, "etag: {}", etag)?;
+    }
+    if let Some(ref last_modified) = entry.last_modified {
+        writeln!(headers, "last-modified: {}", last_modified)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn keys_stay_the_same() {
+        let url = Url::parse("https://example.com/deck.md").unwrap();
+        let other = Url::parse("https://example.com/deck2.md").unwrap();
+        assert_eq!(key(&url), "ff378db5a26935b8");
+        assert!(key(&url) != key(&other));
+    }
+
+    #[test]
+    fn store_and_load() {
+        let dir = env::temp_dir().join(format!("mdp-cache-{}", process::id()));
+        env::set_var("XDG_CACHE_HOME", &dir);
+        let url = Url::parse("https://example.com/deck.md").unwrap();
+        let other = Url::parse("https://example.com/other.md").unwrap();
+        assert!(load(&url).is_none());
+
+        let entry = Entry {
+            body: b"# Slide\n".to_vec(),
+            etag: Some(String::from("\"5a1b-56e4\"")),
+            last_modified: None,
+        };
+        store(&url, &entry).unwrap();
+        let cached = load(&url).unwrap();
+        assert_eq!(cached.body, entry.body);
+        assert_eq!(cached.etag, entry.etag);
+        assert_eq!(cached.last_modified, None);
+        assert!(load(&other).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
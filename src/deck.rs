@@ -1,67 +1,172 @@
+use asset::AssetStore;
+use error::Error;
+use get::Base;
+use meta::{self, DeckMeta};
 use split;
 use std::borrow::Cow;
-use std::io;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 
 #[derive(Default, Debug)]
 pub struct Deck<'a> {
+    meta: DeckMeta,
     slides: Vec<Slide<'a>>,
+    assets: AssetStore,
     current: usize,
+
+    /// How much of the current slide is revealed, see `Slide::step`.
+    step: usize,
 }
 
 #[derive(Default, Debug)]
 pub struct Slide<'a> {
     content: Cow<'a, str>,
     offset: usize,
+
+    /// The first header of the slide, if any.
+    title: Option<String>,
+
+    /// Offsets into `content` where the output stops (`<br>` or `^`).
+    stops: Vec<usize>,
+}
+
+/// What a slide is recognized by after the deck is reloaded: its anchor
+/// (`<!-- id: name -->`) or title, and a hash of its content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlideId {
+    anchor: Option<String>,
+    title: Option<String>,
+    hash: u64,
 }
 
 impl<'a> Slide<'a> {
     pub fn new((offset, content): (usize, Cow<'a, str>)) -> Self {
+        let content = split::align_headers(content);
+        let (content, stops) = split::pauses(content);
         Slide {
-            content: content,
-            offset: offset,
+            content,
+            offset,
+            title: None,
+            stops,
         }
     }
 
     pub fn content(&self) -> Cow<'a, str> {
         self.content.clone()
     }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_ref().map(|title| &title[..])
+    }
+
+    pub fn id(&self) -> SlideId {
+        let mut hasher = DefaultHasher::new();
+        self.content.hash(&mut hasher);
+        SlideId {
+            anchor: split::anchor(&self.content),
+            title: self.title.clone(),
+            hash: hasher.finish(),
+        }
+    }
+
+    /// Number of reveal steps, at least 1.
+    pub fn steps(&self) -> usize {
+        self.stops.len() + 1
+    }
+
+    /// The part of this slide that is shown at step `n`. The last step is the
+    /// full slide.
+    pub fn step(&self, n: usize) -> Slide<'_> {
+        let end = self.stops.get(n).cloned().unwrap_or(self.content.len());
+        Slide {
+            content: Cow::from(&self.content[..end]),
+            offset: self.offset,
+            title: self.title.clone(),
+            stops: Vec::new(),
+        }
+    }
 }
 
 impl<'a> Deck<'a> {
-    pub fn new(content: &'a str) -> io::Result<Deck<'a>> {
-        let slides = split::split(content).map(Slide::new).collect();
+    /// Parse the deck in `content`, which paths in it are relative to `base`.
+    /// Its images are loaded into `assets`.
+    pub fn new(content: &'a str, base: &Base, assets: &AssetStore) -> Result<Deck<'a>, Error> {
+        let (meta, start) = meta::parse(content);
+        let slides = split::split(&content[start..], base, assets)
+            .map(|split| {
+                split.map(|(offset, content, title)| {
+                    let mut slide = Slide::new((start + offset, content));
+                    slide.title = title;
+                    slide
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if slides.is_empty() {
+            return Err(Error::Parse(String::from("the deck has no slides")));
+        }
 
         let deck = Deck {
-            slides: slides,
+            meta,
+            slides,
+            assets: assets.clone(),
             current: 0,
+            step: 0,
         };
 
         Ok(deck)
     }
 
+    pub fn meta(&self) -> &DeckMeta {
+        &self.meta
+    }
+
+    pub fn assets(&self) -> &AssetStore {
+        &self.assets
+    }
+
     pub fn add(&mut self, slide: Slide<'a>) {
         self.slides.push(slide);
     }
 
+    /// Reveal the next step of the current slide, or move to the next slide
+    /// if it's fully shown.
     pub fn next(&mut self) {
-        if self.current < self.slides.len() - 1 {
+        if self.step + 1 < self.slide().steps() {
+            self.step += 1;
+        } else if self.current < self.slides.len() - 1 {
             self.current += 1;
+            self.step = 0;
         }
     }
 
+    /// Hide the last step of the current slide, or move to the previous slide
+    /// (fully shown) if there's nothing left to hide.
     pub fn previous(&mut self) {
-        if self.current > 0 {
+        if self.step > 0 {
+            self.step -= 1;
+        } else if self.current > 0 {
             self.current -= 1;
+            self.step = self.slide().steps() - 1;
         }
     }
 
-    pub fn slide(&self) -> &'a Slide {
+    pub fn slides(&self) -> &[Slide<'a>] {
+        &self.slides
+    }
+
+    pub fn slide(&self) -> &Slide<'a> {
         &self.slides[self.current]
     }
 
+    /// The revealed part of the current slide.
+    pub fn visible(&self) -> Slide<'_> {
+        self.slides[self.current].step(self.step)
+    }
+
     pub fn goto(&mut self, n: usize) {
-        if n < self.slides.len() - 1 {
+        if n < self.slides.len() {
             self.current = n;
+            self.step = 0;
         }
     }
 
@@ -69,7 +174,129 @@ impl<'a> Deck<'a> {
         self.current
     }
 
+    pub fn current_step(&self) -> usize {
+        self.step
+    }
+
     pub fn total_num(&self) -> usize {
         self.slides.len()
     }
+
+    /// The identity of each slide, in order.
+    pub fn ids(&self) -> Vec<SlideId> {
+        self.slides.iter().map(|slide| slide.id()).collect()
+    }
+
+    /// The slide to show after a reload, when slide `current` of a deck with
+    /// slides `ids` was shown: the same slide if it's still there (possibly
+    /// edited), otherwise one next to where its neighbours went.
+    pub fn locate(&self, ids: &[SlideId], current: usize) -> usize {
+        let new = self.ids();
+        let id = match ids.get(current) {
+            Some(id) => id,
+            None => return current.min(new.len().saturating_sub(1)),
+        };
+
+        let found = nearest(&new, current, |other| other == id)
+            .or_else(|| if id.anchor.is_some() {
+                nearest(&new, current, |other| other.anchor == id.anchor)
+            } else {
+                None
+            })
+            .or_else(|| if id.title.is_some() {
+                nearest(&new, current, |other| other.title == id.title)
+            } else {
+                None
+            })
+            .or_else(|| nearest(&new, current, |other| other.hash == id.hash));
+        if let Some(i) = found {
+            return i;
+        }
+
+        // The slide is gone or changed beyond recognition, go to where it was
+        // between its neighbours.
+        for d in 1..ids.len() {
+            if d <= current {
+                if let Some(i) = nearest(&new, current, |other| *other == ids[current - d]) {
+                    return (i + d).min(new.len() - 1);
+                }
+            }
+            if let Some(next) = ids.get(current + d) {
+                if let Some(i) = nearest(&new, current, |other| other == next) {
+                    return i.saturating_sub(d);
+                }
+            }
+        }
+        current.min(new.len().saturating_sub(1))
+    }
+
+    /// The title of each slide, in order.
+    pub fn toc(&self) -> Vec<Option<&str>> {
+        self.slides.iter().map(|slide| slide.title()).collect()
+    }
+}
+
+/// The index of the id matching `f` that's closest to `current`.
+fn nearest<F>(ids: &[SlideId], current: usize, f: F) -> Option<usize>
+where
+    F: Fn(&SlideId) -> bool,
+{
+    ids.iter()
+        .enumerate()
+        .filter(|&(_, id)| f(id))
+        .map(|(i, _)| i)
+        .min_by_key(|&i| i.abs_diff(current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The slide shown after `before` is changed to `after` while slide
+    /// `current` was shown.
+    fn locate(before: &str, current: usize, after: &str) -> usize {
+        let base = Base::of("deck.md");
+        let assets = AssetStore::new();
+        let ids = Deck::new(before, &base, &assets).unwrap().ids();
+        Deck::new(after, &base, &assets).unwrap().locate(&ids, current)
+    }
+
+    const DECK: &str = "# A\n\na\n\n---\n\n# B\n\nb\n\n---\n\n# C\n\nc\n\n---\n\n# D\n\nd\n";
+
+    #[test]
+    fn follows_a_slide_that_moved() {
+        let inserted = format!("# New\n\n---\n\n{}", DECK);
+        assert_eq!(locate(DECK, 2, &inserted), 3);
+    }
+
+    #[test]
+    fn stays_on_an_edited_slide() {
+        let edited = DECK.replace("\nb\n", "\nb, edited\n");
+        assert_eq!(locate(DECK, 1, &edited), 1);
+    }
+
+    #[test]
+    fn goes_where_a_deleted_slide_was() {
+        let deleted = DECK.replace("# C\n\nc\n\n---\n\n", "");
+        assert_eq!(locate(DECK, 2, &deleted), 2);
+    }
+
+    #[test]
+    fn empty_slides() {
+        let deck = "# A\n\n---\n\n---\n\n# C\n";
+        let deck = Deck::new(deck, &Base::of("deck.md"), &AssetStore::new()).unwrap();
+        let slides: Vec<String> = deck.slides()
+            .iter()
+            .map(|slide| String::from(slide.content().trim()))
+            .collect();
+        assert_eq!(slides, ["# A", "", "# C"]);
+    }
+
+    #[test]
+    fn finds_a_slide_by_its_anchor() {
+        let before = "# A\n\n---\n\n<!-- id: here -->\n# B\n\n---\n\n# C\n";
+        let after = "# C\n\n---\n\n<!-- id: here -->\n# Renamed\n\n---\n\n# A\n";
+        assert_eq!(locate(before, 1, after), 1);
+        assert_eq!(locate(before, 2, after), 0);
+    }
 }
@@ -0,0 +1,84 @@
+//! Errors of the library
+
+use img::ImageError;
+use reqwest::{self, StatusCode};
+use std::error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Loading a deck or image from a file or URL failed.
+    Fetch(String, Box<Error>),
+    /// Writing a file failed.
+    Save(String, io::Error),
+    Io(io::Error),
+    Http(reqwest::Error),
+    /// The server answered with an error status.
+    Status(StatusCode),
+    /// A URL is needed that isn't cached while offline.
+    NotCached,
+    /// The deck isn't something we can split into slides.
+    Parse(String),
+    /// A line of a config file is invalid.
+    Config(usize, String),
+    /// Setting up or drawing to the terminal failed.
+    Terminal(io::Error),
+    /// The terminal can't show images.
+    NoImageProtocol,
+    /// The image can't be decoded.
+    Decode(ImageError),
+    /// The image is in a format the terminal can't show.
+    UnsupportedImage(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Fetch(ref location, ref e) => write!(f, "{}: {}", location, e),
+            Error::Save(ref path, ref e) => write!(f, "can't write {}: {}", path, e),
+            Error::Io(ref e) => write!(f, "{}", e),
+            Error::Http(ref e) => write!(f, "{}", e),
+            Error::Status(status) => write!(f, "HTTP {}", status),
+            Error::NotCached => write!(f, "not cached, can't fetch it offline"),
+            Error::Parse(ref why) => write!(f, "can't read the deck: {}", why),
+            Error::Config(line, ref why) => write!(f, "line {}: {}", line, why),
+            Error::Terminal(ref e) => write!(f, "terminal: {}", e),
+            Error::NoImageProtocol => write!(f, "the terminal can't show images"),
+            Error::Decode(ref e) => write!(f, "unsupported image format ({})", e),
+            Error::UnsupportedImage(why) => write!(f, "unsupported image format ({})", why),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Fetch(_, ref e) => Some(&**e),
+            Error::Save(_, ref e) |
+            Error::Io(ref e) |
+            Error::Terminal(ref e) => Some(e),
+            Error::Http(ref e) => Some(e),
+            Error::Decode(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Error {
+        Error::Http(e)
+    }
+}
+
+impl From<ImageError> for Error {
+    fn from(e: ImageError) -> Error {
+        Error::Decode(e)
+    }
+}
@@ -0,0 +1,48 @@
+//! Save a deck as plain text, the way it's shown in the terminal
+
+use backend::Headless;
+use deck::Deck;
+use error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use view::View;
+
+/// Render every slide of `deck`, fully revealed, on a `width` by `height`
+/// screen and write them to `path`, separated by rules. An existing file
+/// isn't overwritten.
+pub fn export(deck: &Deck, width: u16, height: u16, path: &Path) -> Result<(), Error> {
+    let headless = Headless::new(width, height);
+    let mut view = View::with_backend(Box::new(headless.clone()))?;
+    view.set_assets(deck.assets());
+
+    let mut text = String::new();
+    for (i, slide) in deck.slides().iter().enumerate() {
+        view.clear()?;
+        view.present(&slide.step(slide.steps() - 1))?;
+        view.flush()?;
+
+        if i > 0 {
+            text.push_str(&"-".repeat(width as usize));
+            text.push('\n');
+        }
+        let mut lines: Vec<String> = headless.lines()
+            .iter()
+            .map(|line| String::from(line.trim_end()))
+            .collect();
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        for line in lines {
+            text.push_str(&line);
+            text.push('\n');
+        }
+    }
+
+    let write = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut f| f.write_all(text.as_bytes()));
+    write.map_err(|e| Error::Save(path.display().to_string(), e))
+}
@@ -1,37 +1,171 @@
-use reqwest;
+use cache;
+use error::Error;
+use reqwest::{self, StatusCode, Url};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
 use std::fs::File;
-use std::io::{Error, ErrorKind, Read, Result};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-pub fn get_vec(path: &str) -> Result<Vec<u8>> {
-    let mut contents = Vec::new();
-    match reqwest::Url::parse(path) {
-        Ok(url) => {
-            reqwest::get(url)
-                .map_err(|_e| Error::new(ErrorKind::NotConnected, "reqwest"))
-                .and_then(|mut r| r.read_to_end(&mut contents))?;
+/// Where a deck was loaded from, which the paths in it are relative to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Base {
+    Dir(PathBuf),
+    Url(Url),
+}
+
+impl Base {
+    /// The location of the deck at `path` (a file or URL).
+    pub fn of(path: &str) -> Base {
+        match Url::parse(path) {
+            Ok(url) => Base::Url(url),
+            Err(_) => {
+                let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+                if dir.as_os_str().is_empty() {
+                    Base::Dir(PathBuf::from("."))
+                } else {
+                    Base::Dir(dir.to_path_buf())
+                }
+            }
         }
-        Err(_) => {
-            File::open(&path).and_then(
-                |mut r| r.read_to_end(&mut contents),
-            )?;
+    }
+
+    /// Resolve `path` as written in the deck to something `get_vec` can load.
+    /// URLs and absolute paths are left alone.
+    pub fn resolve(&self, path: &str) -> String {
+        if Url::parse(path).is_ok() {
+            return String::from(path);
         }
+        match *self {
+            Base::Dir(ref dir) => {
+                if Path::new(path).is_absolute() {
+                    String::from(path)
+                } else {
+                    dir.join(path).to_string_lossy().into_owned()
+                }
+            }
+            Base::Url(ref url) => {
+                url.join(path).map(|url| url.into_string()).unwrap_or_else(
+                    |_| String::from(path),
+                )
+            }
+        }
+    }
+}
+
+/// The file `path` refers to, unless it's a URL or `-` (stdin).
+pub fn local_file(path: &str) -> Option<PathBuf> {
+    if path == "-" || Url::parse(path).is_ok() {
+        None
+    } else {
+        Some(PathBuf::from(path))
     }
-    Ok(contents)
 }
 
-pub fn get_string(path: &str) -> Result<String> {
-    let mut contents = String::new();
-    match reqwest::Url::parse(path) {
-        Ok(url) => {
-            reqwest::get(url)
-                .map_err(|_e| Error::new(ErrorKind::NotConnected, "reqwest"))
-                .and_then(|mut r| r.read_to_string(&mut contents))?;
+/// Only use cached copies of URLs, don't touch the network.
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// GET `url`, going through the on-disk cache: a cached copy is revalidated
+/// with the server, and used as is when offline or the server can't be
+/// reached.
+fn fetch(url: Url) -> Result<Vec<u8>, Error> {
+    let cached = cache::load(&url);
+    if OFFLINE.load(Ordering::Relaxed) {
+        return cached.map(|entry| entry.body).ok_or(Error::NotCached);
+    }
+
+    let client = reqwest::Client::new();
+    let mut request = client.get(url.clone());
+    if let Some(ref entry) = cached {
+        if let Some(ref etag) = entry.etag {
+            request = request.header(IF_NONE_MATCH, &etag[..]);
+        }
+        if let Some(ref last_modified) = entry.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, &last_modified[..]);
         }
+    }
+
+    let mut response = match (request.send(), cached) {
+        (Ok(response), Some(entry)) => {
+            if response.status() == StatusCode::NOT_MODIFIED {
+                return Ok(entry.body);
+            }
+            response
+        }
+        (Ok(response), None) => response,
+        (Err(e), Some(entry)) => {
+            warn!("{}: {}, using the cached copy", url, e);
+            return Ok(entry.body);
+        }
+        (Err(e), None) => return Err(Error::Http(e)),
+    };
+    if !response.status().is_success() {
+        return Err(Error::Status(response.status()));
+    }
+
+    let header = |name| {
+        response.headers().get(name).and_then(|value| value.to_str().ok()).map(String::from)
+    };
+    let mut entry = cache::Entry {
+        body: Vec::new(),
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+    response.read_to_end(&mut entry.body)?;
+    if let Err(e) = cache::store(&url, &entry) {
+        warn!("failed to cache {}: {}", url, e);
+    }
+    Ok(entry.body)
+}
+
+pub fn get_vec(path: &str) -> Result<Vec<u8>, Error> {
+    let contents = match reqwest::Url::parse(path) {
+        Ok(url) => fetch(url),
         Err(_) => {
-            File::open(&path).and_then(
-                |mut r| r.read_to_string(&mut contents),
-            )?;
+            let mut contents = Vec::new();
+            File::open(path)
+                .and_then(|mut r| r.read_to_end(&mut contents))
+                .map(|_| contents)
+                .map_err(Error::Io)
         }
+    };
+    contents.map_err(|e| Error::Fetch(String::from(path), Box::new(e)))
+}
+
+pub fn get_string(path: &str) -> Result<String, Error> {
+    let contents = get_vec(path)?;
+    String::from_utf8(contents).map_err(|_| {
+        Error::Parse(format!("{} is not UTF-8", path))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_of_a_file() {
+        assert_eq!(Base::of("talk/deck.md"), Base::Dir(PathBuf::from("talk")));
+        assert_eq!(Base::of("deck.md"), Base::Dir(PathBuf::from(".")));
+    }
+
+    #[test]
+    fn resolve_in_a_dir() {
+        let base = Base::of("talk/deck.md");
+        assert_eq!(base.resolve("img/a.png"), "talk/img/a.png");
+        assert_eq!(base.resolve("/img/a.png"), "/img/a.png");
+        assert_eq!(base.resolve("https://example.com/a.png"), "https://example.com/a.png");
+    }
+
+    #[test]
+    fn resolve_on_a_url() {
+        let base = Base::of("https://example.com/talk/deck.md");
+        assert_eq!(base.resolve("img/a.png"), "https://example.com/talk/img/a.png");
+        assert_eq!(base.resolve("../a.png"), "https://example.com/a.png");
+        assert_eq!(base.resolve("/a.png"), "https://example.com/a.png");
     }
-    Ok(contents)
 }
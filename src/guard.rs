@@ -0,0 +1,217 @@
+//! Put the terminal back the way we found it, however the viewer ends
+//!
+//! While a `Guard` lives, keys are read immediately without echo. The
+//! terminal (and the main screen, if the viewer uses the alternate one) is
+//! restored when the guard is dropped, on a panic (before the
+//! message is printed), and around Ctrl+Z. Signals are turned into calls of a
+//! function on a normal thread, as there's little a signal handler may do.
+
+use libc;
+use std::io::{Error, Result, Write, stdout};
+use std::os::unix::io::RawFd;
+use std::panic;
+use std::sync::{Mutex, Once};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use termion::{cursor, screen, style};
+use termios::{ECHO, ICANON, IEXTEN, TCSANOW, Termios, tcsetattr};
+
+/// Signals the viewer acts on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signal {
+    /// SIGINT, SIGTERM or SIGHUP.
+    Quit,
+    /// SIGTSTP (Ctrl+Z), call `suspend`.
+    Suspend,
+    /// SIGCONT after being stopped by someone else.
+    Continue,
+    /// SIGWINCH, the terminal changed its size.
+    Resize,
+}
+
+struct State {
+    fd: RawFd,
+    /// How the terminal was before we started.
+    original: Termios,
+    /// How we want it: no line buffering, no echo.
+    active: Termios,
+    alternate_screen: bool,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<Option<State>> = Mutex::new(None);
+}
+
+/// Write end of the pipe signal handlers write to.
+static SIGNAL_PIPE: AtomicUsize = AtomicUsize::new(0);
+
+/// The panic hook is set once and does nothing while there's no guard.
+static PANIC_HOOK: Once = Once::new();
+
+const SIGNALS: [libc::c_int; 6] = [
+    libc::SIGINT,
+    libc::SIGTERM,
+    libc::SIGHUP,
+    libc::SIGTSTP,
+    libc::SIGCONT,
+    libc::SIGWINCH,
+];
+
+pub struct Guard {
+    /// Write end of the signal pipe.
+    pipe: RawFd,
+    /// The handlers we replaced, put back on drop.
+    previous: Vec<(libc::c_int, libc::sigaction)>,
+}
+
+impl Guard {
+    /// Take over the terminal that keys are read from on `fd`, calling
+    /// `f` from another thread when a signal arrives. With
+    /// `alternate_screen`, the main screen is switched back to on restoring.
+    pub fn new<F>(fd: RawFd, alternate_screen: bool, f: F) -> Result<Guard>
+    where
+        F: Fn(Signal) + Send + 'static,
+    {
+        let original = Termios::from_fd(fd)?;
+        let mut active = original;
+        // local modes: choing off, canonical off, no extended functions
+        active.c_lflag &= !(ICANON | ECHO | IEXTEN);
+
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        SIGNAL_PIPE.store(fds[1] as usize, Ordering::SeqCst);
+        thread::spawn(move || forward(fds[0], f));
+
+        // From here on, dropping the guard undoes what's done so far.
+        let mut guard = Guard {
+            pipe: fds[1],
+            previous: Vec::new(),
+        };
+        for &signal in &SIGNALS {
+            let previous = handle(signal, on_signal as *const () as libc::sighandler_t)?;
+            guard.previous.push((signal, previous));
+        }
+
+        PANIC_HOOK.call_once(|| {
+            let previous = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                restore();
+                previous(info);
+            }));
+        });
+
+        tcsetattr(fd, TCSANOW, &active)?;
+        *STATE.lock().unwrap() = Some(State {
+            fd,
+            original,
+            active,
+            alternate_screen,
+        });
+        Ok(guard)
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        restore();
+        if let Ok(mut state) = STATE.lock() {
+            *state = None;
+        }
+        for &(signal, ref action) in &self.previous {
+            unsafe { libc::sigaction(signal, action, ::std::ptr::null_mut()) };
+        }
+        // Ends the thread forwarding signals
+        unsafe { libc::close(self.pipe) };
+    }
+}
+
+/// Reset colors, show the cursor, switch to the main screen and restore the
+/// original terminal modes, if a guard took them over.
+fn restore() {
+    // Don't wait for the lock when panicking with it held
+    if let Ok(state) = STATE.try_lock() {
+        if let Some(ref state) = *state {
+            let mut out = stdout();
+            let _ = write!(out, "{}{}", style::Reset, cursor::Show);
+            if state.alternate_screen {
+                let _ = write!(out, "{}", screen::ToMainScreen);
+            }
+            let _ = out.flush();
+            if let Err(e) = tcsetattr(state.fd, TCSANOW, &state.original) {
+                error!("failed to reset the input terminal {}", e);
+            }
+        }
+    }
+}
+
+/// Bring the terminal back into the modes the viewer needs, after it was
+/// stopped.
+pub fn resume() {
+    if let Some(ref state) = *STATE.lock().unwrap() {
+        if state.alternate_screen {
+            let mut out = stdout();
+            let _ = write!(out, "{}", screen::ToAlternateScreen);
+            let _ = out.flush();
+        }
+        if let Err(e) = tcsetattr(state.fd, TCSANOW, &state.active) {
+            error!("failed to set the input terminal {}", e);
+        }
+    }
+}
+
+/// Restore the terminal and stop the process like Ctrl+Z does without us.
+/// Returns once it's continued (`fg`), with the terminal ready again; the
+/// screen needs to be redrawn.
+pub fn suspend() -> Result<()> {
+    restore();
+    handle(libc::SIGTSTP, libc::SIG_DFL)?;
+    unsafe { libc::raise(libc::SIGTSTP) };
+    handle(libc::SIGTSTP, on_signal as *const () as libc::sighandler_t)?;
+    resume();
+    Ok(())
+}
+
+/// Call `handler` on `signal`, returning the action that was set before.
+fn handle(signal: libc::c_int, handler: libc::sighandler_t) -> Result<libc::sigaction> {
+    unsafe {
+        let mut action: libc::sigaction = ::std::mem::zeroed();
+        let mut previous: libc::sigaction = ::std::mem::zeroed();
+        action.sa_sigaction = handler;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signal, &action, &mut previous) != 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(previous)
+    }
+}
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    let fd = SIGNAL_PIPE.load(Ordering::SeqCst) as RawFd;
+    let byte = signal as u8;
+    unsafe { libc::write(fd, &byte as *const u8 as *const libc::c_void, 1) };
+}
+
+/// Read signals from the pipe and hand them to `f`.
+fn forward<F: Fn(Signal)>(fd: RawFd, f: F) {
+    let mut byte = 0u8;
+    loop {
+        let n = unsafe { libc::read(fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+        if n < 0 && Error::last_os_error().kind() == ::std::io::ErrorKind::Interrupted {
+            continue;
+        }
+        if n <= 0 {
+            unsafe { libc::close(fd) };
+            return;
+        }
+        let signal = match byte as libc::c_int {
+            libc::SIGTSTP => Signal::Suspend,
+            libc::SIGCONT => Signal::Continue,
+            libc::SIGWINCH => Signal::Resize,
+            _ => Signal::Quit,
+        };
+        f(signal);
+    }
+}
@@ -1,68 +1,486 @@
-/// To display inline image, we use the following code:
-///
-/// ```ignore
-/// ESC ] 1337 ; File = [optional arguments] : base-64 encoded file contents ^G
-/// ```
+//! Show images in the terminal.
+//!
+//! Three protocols are supported, picked by looking at the environment (or
+//! forced with `MDP_IMAGE_PROTOCOL=iterm2|kitty|sixel|halfblocks|none`):
+//!
+//! iTerm2's inline images:
+//!
+//! ```ignore
+//! ESC ] 1337 ; File = [optional arguments] : base-64 encoded file contents ^G
+//! ```
+//!
+//! kitty's graphics protocol (also understood by WezTerm and Ghostty), where
+//! a base-64 encoded PNG is sent in chunks of at most 4096 bytes. Other
+//! formats are converted to PNG first:
+//!
+//! ```ignore
+//! ESC _ G a=T,f=100,m=1 ; first chunk ESC \
+//! ESC _ G m=1 ; next chunk ESC \
+//! ESC _ G m=0 ; last chunk ESC \
+//! ```
+//!
+//! DEC sixel graphics (xterm, foot, mlterm, Windows Terminal), where the image
+//! is decoded, scaled and drawn with a palette, six pixel rows at a time:
+//!
+//! ```ignore
+//! ESC P 0 ; 1 ; 0 q " 1 ; 1 ; width ; height  #color ... sixels ... ESC \
+//! ```
+//!
+//! Other terminals get the image drawn with `▀` characters, each cell showing
+//! two pixels with its foreground and background color. Colors are 24-bit if
+//! `COLORTERM` says so and from the 256 color palette otherwise.
 use base64::encode;
-use get::get_vec;
+use buffer::{Color, Style};
+use img::{self, ColorType, FilterType, GenericImage, RgbaImage};
+use img::png::PNGEncoder;
+use libc;
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::env::var;
-use std::io::{Error, ErrorKind, Result, Write};
+use error::Error;
+use std::io::{self, Write};
+use std::mem;
 use std::sync::Mutex;
 
-fn support_image() -> bool {
-    match var("TERM_PROGRAM") {
-        Ok(term) => term == "iTerm.app",
-        Err(_) => false,
+/// Terminal graphics protocols we can show images with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    ITerm2,
+    Kitty,
+    Sixel,
+    /// Not a protocol: the image is drawn into cells by the view.
+    HalfBlocks { truecolor: bool },
+}
+
+/// The cells an image may take. Images that are larger are scaled down,
+/// keeping their aspect ratio.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Placement {
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+
+    /// Scale smaller images up to fill the cells as well.
+    pub grow: bool,
+}
+
+fn detect() -> Option<Protocol> {
+    if let Ok(protocol) = var("MDP_IMAGE_PROTOCOL") {
+        match &protocol[..] {
+            "iterm2" => return Some(Protocol::ITerm2),
+            "kitty" => return Some(Protocol::Kitty),
+            "sixel" => return Some(Protocol::Sixel),
+            "halfblocks" => return Some(Protocol::HalfBlocks { truecolor: truecolor() }),
+            "none" => return None,
+            _ => warn!("unknown image protocol {}", protocol),
+        }
+    }
+
+    let term = var("TERM").unwrap_or_default();
+    let program = var("TERM_PROGRAM").unwrap_or_default();
+    if program == "iTerm.app" {
+        Some(Protocol::ITerm2)
+    } else if term == "xterm-kitty" || var("KITTY_WINDOW_ID").is_ok() ||
+               program == "WezTerm" || program == "ghostty" ||
+               term == "xterm-ghostty"
+    {
+        Some(Protocol::Kitty)
+    } else if term.starts_with("foot") || term.starts_with("mlterm") ||
+               term.contains("sixel") || var("MLTERM").is_ok() ||
+               var("WT_SESSION").is_ok()
+    {
+        Some(Protocol::Sixel)
+    } else if term.is_empty() || term == "dumb" || term == "linux" {
+        None
+    } else {
+        Some(Protocol::HalfBlocks { truecolor: truecolor() })
+    }
+}
+
+fn truecolor() -> bool {
+    matches!(&var("COLORTERM").unwrap_or_default()[..], "truecolor" | "24bit")
+}
+
+/// The protocol images are shown with, if any.
+pub fn protocol() -> Option<Protocol> {
+    *PROTOCOL
+}
+
+/// Size of an image of `width` x `height` pixels shown in `placement`, in
+/// pixels and in cells.
+fn fit(width: u32, height: u32, placement: Placement) -> ((u32, u32), (u16, u16)) {
+    let (cell_width, cell_height) = cell_size();
+    let (width, height) = (width.max(1), height.max(1));
+    let max_width = placement.cols.map_or(width, |cols| cols as u32 * cell_width);
+    let max_height = placement.rows.map_or(height, |rows| rows as u32 * cell_height);
+    let mut scale = f64::min(
+        max_width as f64 / width as f64,
+        max_height as f64 / height as f64,
+    );
+    if !placement.grow && scale > 1.0 {
+        scale = 1.0;
+    }
+
+    let width = ((width as f64 * scale) as u32).max(1);
+    let height = ((height as f64 * scale) as u32).max(1);
+    let cols = width.div_ceil(cell_width);
+    let rows = height.div_ceil(cell_height);
+    ((width, height), (cols as u16, rows as u16))
+}
+
+/// Size of a cell in pixels, assuming 10x20 if the terminal doesn't say.
+fn cell_size() -> (u32, u32) {
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    let ret = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if ret == 0 && size.ws_col > 0 && size.ws_row > 0 && size.ws_xpixel > 0 &&
+        size.ws_ypixel > 0
+    {
+        (
+            size.ws_xpixel as u32 / size.ws_col as u32,
+            size.ws_ypixel as u32 / size.ws_row as u32,
+        )
+    } else {
+        (10, 20)
     }
 }
 
-fn print_osc<W: Write>(buf: &mut W) -> Result<()> {
-    write!(buf, "{}]", '\u{1B}')
+fn print_osc<W: Write>(buf: &mut W) -> io::Result<()> {
+    write!(buf, "\u{1B}]")
 }
 
-fn print_st<W: Write>(buf: &mut W) -> Result<()> {
+fn print_st<W: Write>(buf: &mut W) -> io::Result<()> {
     // char::from(7) is equivalent to \a (not sure why)
     write!(buf, "{}", char::from(7))
 }
 
+fn print_apc<W: Write>(buf: &mut W) -> io::Result<()> {
+    write!(buf, "\u{1B}_G")
+}
+
+fn print_esc_st<W: Write>(buf: &mut W) -> io::Result<()> {
+    write!(buf, "\u{1B}\\")
+}
+
 lazy_static! {
-    static ref IMAGE_STORE: Mutex<HashMap<String, String>> = {
-        Mutex::new(HashMap::new())
-    };
+    static ref PROTOCOL: Option<Protocol> = detect();
 }
 
-pub fn inline_image<W>(buf: &mut W, name: &str) -> Result<()>
+/// An encoded sixel image with its size in cells.
+type Sixel = (Vec<u8>, (u16, u16));
+
+/// Rows of cells drawn with half blocks.
+type HalfBlocks = Vec<Vec<(char, Style)>>;
+
+/// The contents of an image file, with what's derived from them as decoding
+/// and scaling is too slow to do for every frame.
+pub struct Image {
+    data: Vec<u8>,
+
+    /// Size in pixels, `None` inside if it can't be decoded.
+    dimensions: Mutex<Option<Option<(u32, u32)>>>,
+
+    /// The image as PNG for kitty, if it's in another format.
+    png: Mutex<Option<Vec<u8>>>,
+
+    /// Encoded sixel images with their size in cells.
+    sixels: Mutex<HashMap<Placement, Sixel>>,
+
+    half_blocks: Mutex<HashMap<Placement, HalfBlocks>>,
+}
+
+impl Image {
+    pub fn new(data: Vec<u8>) -> Image {
+        Image {
+            data,
+            dimensions: Mutex::new(None),
+            png: Mutex::new(None),
+            sixels: Mutex::new(HashMap::new()),
+            half_blocks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Size in pixels, decoding the image the first time.
+    fn dimensions(&self) -> Option<(u32, u32)> {
+        let mut dimensions = self.dimensions.lock().unwrap();
+        if dimensions.is_none() {
+            let decoded = img::load_from_memory(&self.data).ok();
+            *dimensions = Some(decoded.map(|image| image.dimensions()));
+        }
+        dimensions.unwrap()
+    }
+}
+
+/// Draw `image` at the cursor. Returns the number of columns and rows the
+/// image takes.
+pub fn inline_image<W>(buf: &mut W, image: &Image, placement: Placement) -> Result<(u16, u16), Error>
 where
     W: Write,
 {
-    if !support_image() {
-        return Err(Error::new(ErrorKind::Other, "inline image not supported"));
+    let protocol = match *PROTOCOL {
+        Some(protocol) => protocol,
+        None => return Err(Error::NoImageProtocol),
+    };
+
+    match protocol {
+        Protocol::ITerm2 => {
+            // iTerm2 shows more formats than we can decode, those just get
+            // all of the cells.
+            let size = match image.dimensions() {
+                Some((width, height)) => fit(width, height, placement).1,
+                None => (placement.cols.unwrap_or(1), placement.rows.unwrap_or(1)),
+            };
+            iterm2(buf, &image.data, size)?;
+            Ok(size)
+        }
+        Protocol::Kitty => {
+            let size = match image.dimensions() {
+                Some((width, height)) => fit(width, height, placement).1,
+                None => return Err(Error::UnsupportedImage("can't read its size")),
+            };
+            if image.data.starts_with(PNG_SIGNATURE) {
+                kitty(buf, &image.data, size)?;
+            } else {
+                let mut png = image.png.lock().unwrap();
+                if png.is_none() {
+                    *png = Some(to_png(&image.data)?);
+                }
+                kitty(buf, png.as_ref().unwrap(), size)?;
+            }
+            Ok(size)
+        }
+        Protocol::Sixel => {
+            let mut cache = image.sixels.lock().unwrap();
+            let &mut (ref data, size) = match cache.entry(placement) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(sixel(&image.data, placement)?),
+            };
+            buf.write_all(data)?;
+            Ok(size)
+        }
+        Protocol::HalfBlocks { .. } => {
+            // Drawn into cells by the view, see `half_blocks`
+            Err(Error::NoImageProtocol)
+        }
+    }
+}
+
+/// `image` as rows of cells to draw with the `HalfBlocks` protocol.
+pub fn half_blocks(image: &Image, placement: Placement) -> Result<HalfBlocks, Error> {
+    let truecolor = match *PROTOCOL {
+        Some(Protocol::HalfBlocks { truecolor }) => truecolor,
+        _ => return Err(Error::NoImageProtocol),
+    };
+
+    let mut cache = image.half_blocks.lock().unwrap();
+    if let Some(cells) = cache.get(&placement) {
+        return Ok(cells.clone());
     }
 
-    let store = IMAGE_STORE.lock().unwrap();
-    let image = match store.get(name) {
-        Some(image) => image,
-        None => return Err(Error::new(ErrorKind::Other, "image not found")),
+    let cells = {
+        let image = img::load_from_memory(&image.data)?;
+
+        // As many cells as a graphics protocol would take
+        let (width, height) = image.dimensions();
+        let (_, (cols, rows)) = fit(width, height, placement);
+        let (cols, rows) = (cols as u32, rows as u32);
+
+        let image = image.resize_exact(cols, rows * 2, FilterType::Triangle).to_rgba();
+        let color = |x, y| {
+            let p = image.get_pixel(x, y).data;
+            if p[3] < 128 {
+                None
+            } else if truecolor {
+                Some(Color::Rgb(p[0], p[1], p[2]))
+            } else {
+                Some(ansi_color(p))
+            }
+        };
+        (0..rows)
+            .map(|row| {
+                (0..cols)
+                    .map(|x| half_block(color(x, row * 2), color(x, row * 2 + 1)))
+                    .collect()
+            })
+            .collect::<Vec<_>>()
     };
+    cache.insert(placement, cells.clone());
+    Ok(cells)
+}
+
+/// A cell showing `top` over `bottom`, where `None` is transparent.
+fn half_block(top: Option<Color>, bottom: Option<Color>) -> (char, Style) {
+    let mut style = Style::default();
+    let c = match (top, bottom) {
+        (Some(top), bottom) => {
+            style.fg = top;
+            style.bg = bottom.unwrap_or(Color::Reset);
+            '▀'
+        }
+        (None, Some(bottom)) => {
+            style.fg = bottom;
+            '▄'
+        }
+        (None, None) => ' ',
+    };
+    (c, style)
+}
 
+/// The closest color of the 256 color palette's 6x6x6 color cube.
+fn ansi_color(rgba: [u8; 4]) -> Color {
+    let level = |v: u8| (v as u16 * 5 + 127) / 255;
+    Color::Ansi((16 + level(rgba[0]) * 36 + level(rgba[1]) * 6 + level(rgba[2])) as u8)
+}
+
+/// Remove the images drawn with `inline_image` from the screen, for protocols
+/// where clearing the screen doesn't.
+pub fn clear_images<W: Write>(buf: &mut W) -> io::Result<()> {
+    match *PROTOCOL {
+        Some(Protocol::Kitty) => {
+            print_apc(buf)?;
+            write!(buf, "a=d,q=2")?;
+            print_esc_st(buf)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn iterm2<W: Write>(buf: &mut W, image: &[u8], (cols, rows): (u16, u16)) -> io::Result<()> {
     print_osc(buf)?;
     write!(buf, "1337;File=")?;
-    write!(buf, "inline=1")?;
-
-    // TODO(benzh) print other optional arguments, such as size?
+    write!(buf, "inline=1;")?;
+    write!(buf, "width={};height={};preserveAspectRatio=1", cols, rows)?;
     write!(buf, ":")?;
-    write!(buf, "{}", image)?;
+    write!(buf, "{}", encode(image))?;
     print_st(buf)?;
-    write!(buf, "\n")?;
     Ok(())
 }
 
-pub fn retrieve_image(path: String) {
-    if let Ok(content) = get_vec(&path) {
-        let base64 = encode(&content);
-        let mut store = IMAGE_STORE.lock().unwrap();
-        println!("inserting {}", path);
-        store.insert(path, base64);
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Decode `image` and encode it as PNG.
+fn to_png(image: &[u8]) -> Result<Vec<u8>, Error> {
+    let image = img::load_from_memory(image)?.to_rgba();
+    let mut png = Vec::new();
+    PNGEncoder::new(&mut png).encode(&image, image.width(), image.height(), ColorType::RGBA(8))?;
+    Ok(png)
+}
+
+/// Show `image`, which has to be a PNG, in `cols` by `rows` cells.
+fn kitty<W: Write>(buf: &mut W, image: &[u8], (cols, rows): (u16, u16)) -> io::Result<()> {
+    let data = encode(image);
+    let chunks = data.as_bytes().chunks(4096).count();
+    for (i, chunk) in data.as_bytes().chunks(4096).enumerate() {
+        print_apc(buf)?;
+        if i == 0 {
+            // Transmit and display a PNG, don't answer
+            write!(buf, "a=T,f=100,q=2,c={},r={},", cols, rows)?;
+        }
+        let more = if i + 1 < chunks { 1 } else { 0 };
+        write!(buf, "m={};", more)?;
+        buf.write_all(chunk)?;
+        print_esc_st(buf)?;
     }
+    Ok(())
+}
+
+/// Decode `image` and encode it as sixels, scaled to fit `placement`.
+/// Returns the sixel data and the cells it takes.
+fn sixel(image: &[u8], placement: Placement) -> Result<Sixel, Error> {
+    let image = img::load_from_memory(image)?;
+
+    let (width, height) = image.dimensions();
+    let ((width, height), size) = fit(width, height, placement);
+    let image = image
+        .resize_exact(width, height, FilterType::Triangle)
+        .to_rgba();
+
+    let mut buf = Vec::new();
+    write_sixel(&mut buf, &image)?;
+    Ok((buf, size))
+}
+
+/// Index of the closest color in a 6x6x6 color cube, `None` if transparent.
+fn palette_index(rgba: [u8; 4]) -> Option<usize> {
+    if rgba[3] < 128 {
+        return None;
+    }
+    let level = |v: u8| (v as usize * 5 + 127) / 255;
+    Some(level(rgba[0]) * 36 + level(rgba[1]) * 6 + level(rgba[2]))
+}
+
+fn write_sixel<W: Write>(buf: &mut W, image: &RgbaImage) -> io::Result<()> {
+    let (width, height) = image.dimensions();
+
+    // Transparent pixels keep the background (P2 = 1)
+    write!(buf, "\u{1B}P0;1;0q")?;
+    write!(buf, "\"1;1;{};{}", width, height)?;
+    for i in 0..216 {
+        let percent = |v: usize| v * 100 / 5;
+        write!(
+            buf,
+            "#{};2;{};{};{}",
+            i,
+            percent(i / 36),
+            percent(i / 6 % 6),
+            percent(i % 6)
+        )?;
+    }
+
+    let mut indices = vec![None; width as usize];
+    for band in 0..height.div_ceil(6) {
+        // sixels[color][x] has bit i set if pixel (x, band * 6 + i) has color
+        let mut sixels: HashMap<usize, Vec<u8>> = HashMap::new();
+        for dy in 0..6 {
+            let y = band * 6 + dy;
+            if y >= height {
+                break;
+            }
+            for x in 0..width {
+                indices[x as usize] = palette_index(image.get_pixel(x, y).data);
+            }
+            for (x, index) in indices.iter().enumerate() {
+                if let Some(index) = *index {
+                    let row = sixels.entry(index).or_insert_with(
+                        || vec![0; width as usize],
+                    );
+                    row[x] |= 1 << dy;
+                }
+            }
+        }
+
+        let mut colors: Vec<_> = sixels.keys().cloned().collect();
+        colors.sort();
+        for (n, color) in colors.iter().enumerate() {
+            if n > 0 {
+                // Back to the start of the band
+                write!(buf, "$")?;
+            }
+            write!(buf, "#{}", color)?;
+            write_sixel_row(buf, &sixels[color])?;
+        }
+        write!(buf, "-")?;
+    }
+
+    write!(buf, "\u{1B}\\")
+}
+
+/// Write one color of a band, run-length encoded.
+fn write_sixel_row<W: Write>(buf: &mut W, row: &[u8]) -> io::Result<()> {
+    let mut i = 0;
+    while i < row.len() {
+        let bits = row[i];
+        let mut run = 1;
+        while i + run < row.len() && row[i + run] == bits {
+            run += 1;
+        }
+        let c = (63 + bits) as char;
+        if run > 3 {
+            write!(buf, "!{}{}", run, c)?;
+        } else {
+            for _ in 0..run {
+                write!(buf, "{}", c)?;
+            }
+        }
+        i += run;
+    }
+    Ok(())
 }
@@ -0,0 +1,346 @@
+//! What the keys do
+//!
+//! The defaults are the ones from the guide. They can be changed in
+//! `$XDG_CONFIG_HOME/mdp/keys` (or `~/.config/mdp/keys`), one binding per
+//! line:
+//!
+//! ```ignore
+//! # comments and empty lines are skipped
+//! n = next
+//! ctrl-p = previous
+//! f5 = goto 1
+//! l = none
+//! ```
+
+use error::Error;
+use std::collections::HashMap;
+use std::env::var_os;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::PathBuf;
+use termion::event::Key;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Next,
+    Previous,
+    First,
+    Last,
+    /// Go to the slide with this index.
+    Goto(usize),
+    /// Show the table of contents.
+    Toc,
+    Reload,
+    /// Open the `:` command line.
+    Command,
+    Help,
+    Quit,
+}
+
+impl Action {
+    /// The action named `s`, as in the config file.
+    pub fn parse(s: &str) -> Option<Action> {
+        let mut words = s.split_whitespace();
+        let action = match words.next()? {
+            "next" => Action::Next,
+            "previous" | "prev" => Action::Previous,
+            "first" => Action::First,
+            "last" => Action::Last,
+            "goto" => {
+                let n = words.next()?.parse::<usize>().ok()?;
+                Action::Goto(n.checked_sub(1)?)
+            }
+            "toc" => Action::Toc,
+            "reload" => Action::Reload,
+            "command" => Action::Command,
+            "help" => Action::Help,
+            "quit" => Action::Quit,
+            _ => return None,
+        };
+        match words.next() {
+            Some(_) => None,
+            None => Some(action),
+        }
+    }
+
+    /// What the action does, for the help screen.
+    fn describe(&self) -> &'static str {
+        match *self {
+            Action::Next => "next slide",
+            Action::Previous => "previous slide",
+            Action::First => "first slide",
+            Action::Last => "last slide",
+            Action::Goto(_) => "slide N",
+            Action::Toc => "table of contents",
+            Action::Reload => "reload",
+            Action::Command => "command line",
+            Action::Help => "help",
+            Action::Quit => "quit",
+        }
+    }
+}
+
+/// Names of keys that aren't a single character.
+const NAMES: [(&str, Key); 17] = [
+    ("space", Key::Char(' ')),
+    ("enter", Key::Char('\n')),
+    ("tab", Key::Char('\t')),
+    ("backspace", Key::Backspace),
+    ("esc", Key::Esc),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+    ("backtab", Key::BackTab),
+    ("delete", Key::Delete),
+    ("insert", Key::Insert),
+    ("null", Key::Null),
+];
+
+/// `x`, `ctrl-x`, `alt-x`, `f1`..`f12` or one of `NAMES`.
+fn parse_key(s: &str) -> Option<Key> {
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+
+    if let Some(c) = single(s) {
+        return Some(Key::Char(c));
+    }
+    let lower = s.to_lowercase();
+    if let Some(&(_, key)) = NAMES.iter().find(|&&(name, _)| name == lower) {
+        return Some(key);
+    }
+    if lower.starts_with("ctrl-") {
+        return single(&s[5..]).map(Key::Ctrl);
+    }
+    if lower.starts_with("alt-") {
+        return single(&s[4..]).map(Key::Alt);
+    }
+    if let Some(n) = lower.strip_prefix('f') {
+        return match n.parse::<u8>() {
+            Ok(n) if (1..=12).contains(&n) => Some(Key::F(n)),
+            _ => None,
+        };
+    }
+    None
+}
+
+fn key_name(key: &Key) -> String {
+    if let Some(&(name, _)) = NAMES.iter().find(|&&(_, k)| k == *key) {
+        return String::from(name);
+    }
+    match *key {
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::Alt(c) => format!("alt-{}", c),
+        Key::F(n) => format!("f{}", n),
+        _ => format!("{:?}", key),
+    }
+}
+
+fn config_file() -> Option<PathBuf> {
+    let config = match var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("mdp").join("keys"))
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    actions: HashMap<Key, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let mut actions = HashMap::new();
+        for &key in &[
+            Key::Char('\n'),
+            Key::Char(' '),
+            Key::PageDown,
+            Key::Char('j'),
+            Key::Char('l'),
+            Key::Down,
+            Key::Right,
+        ]
+        {
+            actions.insert(key, Action::Next);
+        }
+        for &key in &[
+            Key::Backspace,
+            Key::PageUp,
+            Key::Char('h'),
+            Key::Char('k'),
+            Key::Up,
+            Key::Left,
+        ]
+        {
+            actions.insert(key, Action::Previous);
+        }
+        for n in 1..10 {
+            let c = ::std::char::from_digit(n as u32, 10).unwrap();
+            actions.insert(Key::Char(c), Action::Goto(n - 1));
+        }
+        actions.insert(Key::Home, Action::First);
+        actions.insert(Key::Char('g'), Action::First);
+        actions.insert(Key::End, Action::Last);
+        actions.insert(Key::Char('G'), Action::Last);
+        actions.insert(Key::Esc, Action::Toc);
+        actions.insert(Key::Char('r'), Action::Reload);
+        actions.insert(Key::Char(':'), Action::Command);
+        actions.insert(Key::Char('?'), Action::Help);
+        actions.insert(Key::Char('q'), Action::Quit);
+        Keymap { actions }
+    }
+}
+
+impl Keymap {
+    /// The default keymap with the bindings of the user's config file.
+    pub fn load() -> Result<Keymap, Error> {
+        let mut keymap = Keymap::default();
+        let path = match config_file() {
+            Some(path) => path,
+            None => return Ok(keymap),
+        };
+
+        let mut content = String::new();
+        match File::open(&path).and_then(|mut f| f.read_to_string(&mut content)) {
+            Ok(_) => {}
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(keymap),
+            Err(e) => return Err(Error::Fetch(path.display().to_string(), Box::new(Error::Io(e)))),
+        }
+        keymap.bind(&content).map_err(|e| {
+            Error::Fetch(path.display().to_string(), Box::new(e))
+        })?;
+        Ok(keymap)
+    }
+
+    /// Apply the bindings in `config`, in the format of the config file.
+    pub fn bind(&mut self, config: &str) -> Result<(), Error> {
+        for (i, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |why: String| Error::Config(i + 1, why);
+
+            // Split at the last `=`, so that `=` can be bound too
+            let mut kv = line.rsplitn(2, '=');
+            let (action, key) = match (kv.next(), kv.next()) {
+                (Some(action), Some(key)) => (action.trim(), key.trim()),
+                _ => return Err(invalid(String::from("expected `key = action`"))),
+            };
+            let key = parse_key(key).ok_or_else(|| {
+                invalid(format!("unknown key `{}`", key))
+            })?;
+            if action == "none" {
+                self.actions.remove(&key);
+                continue;
+            }
+            let action = Action::parse(action).ok_or_else(|| {
+                invalid(format!("unknown action `{}`", action))
+            })?;
+            self.actions.insert(key, action);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, key: &Key) -> Option<Action> {
+        self.actions.get(key).cloned()
+    }
+
+    /// Lines of the help screen: what each action does and its keys.
+    pub fn help(&self) -> Vec<String> {
+        let order = [
+            Action::Next,
+            Action::Previous,
+            Action::First,
+            Action::Last,
+            Action::Goto(0),
+            Action::Toc,
+            Action::Reload,
+            Action::Command,
+            Action::Help,
+            Action::Quit,
+        ];
+        let same = |a: &Action, b: &Action| match (*a, *b) {
+            (Action::Goto(_), Action::Goto(_)) => true,
+            (a, b) => a == b,
+        };
+
+        let mut lines = Vec::new();
+        for action in &order {
+            let mut keys: Vec<(Key, Action)> = self.actions
+                .iter()
+                .filter(|&(_, a)| same(a, action))
+                .map(|(&k, &a)| (k, a))
+                .collect();
+            if keys.is_empty() {
+                continue;
+            }
+            keys.sort_by_key(|&(k, a)| (slide_of(a), key_name(&k)));
+            let names = keys.iter()
+                .map(|&(k, _)| key_name(&k))
+                .collect::<Vec<_>>()
+                .join(", ");
+            lines.push(format!("{:<18}{}", action.describe(), names));
+        }
+        lines
+    }
+}
+
+/// The slide of a goto action, to list their keys in order.
+fn slide_of(action: Action) -> usize {
+    match action {
+        Action::Goto(n) => n,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.get(&Key::Char('\n')), Some(Action::Next));
+        assert_eq!(keymap.get(&Key::Backspace), Some(Action::Previous));
+        assert_eq!(keymap.get(&Key::Char('G')), Some(Action::Last));
+        assert_eq!(keymap.get(&Key::Char('3')), Some(Action::Goto(2)));
+        assert_eq!(keymap.get(&Key::Char('x')), None);
+    }
+
+    #[test]
+    fn bind() {
+        let mut keymap = Keymap::default();
+        let config = "# comment\n\nn = next\nctrl-p = prev\nF5 = goto 10\n= = first\nl = none\n";
+        keymap.bind(config).unwrap();
+        assert_eq!(keymap.get(&Key::Char('n')), Some(Action::Next));
+        assert_eq!(keymap.get(&Key::Ctrl('p')), Some(Action::Previous));
+        assert_eq!(keymap.get(&Key::F(5)), Some(Action::Goto(9)));
+        assert_eq!(keymap.get(&Key::Char('=')), Some(Action::First));
+        assert_eq!(keymap.get(&Key::Char('l')), None);
+        assert_eq!(keymap.get(&Key::Char('j')), Some(Action::Next));
+    }
+
+    #[test]
+    fn bind_errors() {
+        let line = |config: &str| match Keymap::default().bind(config) {
+            Err(Error::Config(line, _)) => line,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(line("n = next\nx = lats"), 2);
+        assert_eq!(line("\n\nctrl-xy = next"), 3);
+        assert_eq!(line("just words"), 1);
+        assert_eq!(line("g = goto 0"), 1);
+    }
+}
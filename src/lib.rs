@@ -4,23 +4,44 @@
 #[macro_use]
 extern crate lazy_static;
 extern crate base64;
+extern crate image as img;
+extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate termion;
 extern crate termios;
 extern crate pulldown_cmark;
 extern crate reqwest;
+extern crate unicode_width;
 
+mod asset;
+mod backend;
+mod buffer;
+mod cache;
 mod deck;
-mod input;
+mod error;
+mod export;
+mod guard;
+mod keymap;
+mod meta;
 mod present;
 mod split;
+mod theme;
 mod view;
 mod viewer;
+mod watch;
 mod image;
 mod get;
 
-pub use deck::Deck;
-pub use image::inline_image;
+pub use asset::{Asset, AssetStore};
+pub use backend::{Backend, Headless, Termion};
+pub use buffer::{Buffer, Cell, Color, Style};
+pub use deck::{Deck, Slide, SlideId};
+pub use error::Error;
+pub use get::{Base, set_offline};
+pub use image::{Image, Placement, Protocol, inline_image};
+pub use meta::DeckMeta;
 pub use present::Present;
+pub use theme::Theme;
+pub use view::View;
 pub use viewer::play;
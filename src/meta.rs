@@ -0,0 +1,143 @@
+//! Parse the metadata block at the top of a markdown file
+//!
+//! A deck may start with a few `key: value` lines (terminated by an empty
+//! line), for example:
+//!
+//! ```ignore
+//! title: mdp - Sample Presentation
+//! author: visit1985
+//! date: 2016-02-07
+//! ```
+//!
+//! These lines are not part of the first slide. To tell them from a slide
+//! that starts with something like `Note: ...`, at least one of `title`,
+//! `author` and `date` has to be given.
+
+use std::collections::HashMap;
+
+#[derive(Default, Debug, Clone)]
+pub struct DeckMeta {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub date: Option<String>,
+
+    /// Any other keys found in the metadata block.
+    pub extra: HashMap<String, String>,
+}
+
+impl DeckMeta {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        match key {
+            "title" => self.title.as_ref().map(|s| &s[..]),
+            "author" => self.author.as_ref().map(|s| &s[..]),
+            "date" => self.date.as_ref().map(|s| &s[..]),
+            _ => self.extra.get(key).map(|s| &s[..]),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.author.is_none() && self.date.is_none() &&
+            self.extra.is_empty()
+    }
+
+    /// Whether there's a title, author or date.
+    fn has_known(&self) -> bool {
+        self.title.is_some() || self.author.is_some() || self.date.is_some()
+    }
+
+    fn insert(&mut self, key: &str, value: &str) {
+        let value = String::from(value);
+        match key {
+            "title" => self.title = Some(value),
+            "author" => self.author = Some(value),
+            "date" => self.date = Some(value),
+            _ => {
+                self.extra.insert(String::from(key), value);
+            }
+        }
+    }
+}
+
+/// Split `line` into a `(key, value)` pair if it looks like `key: value`.
+fn key_value(line: &str) -> Option<(&str, &str)> {
+    let colon = line.find(':')?;
+    let key = &line[..colon];
+    let valid_key = !key.is_empty() &&
+        key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    if !valid_key {
+        return None;
+    }
+    Some((key, line[colon + 1..].trim()))
+}
+
+/// Parse the metadata block of `buf`.
+///
+/// Returns the metadata together with the offset where the slides start. If
+/// the file doesn't begin with a metadata block, the offset is 0. Leading
+/// `key: value` lines without a title, author or date are slide content.
+pub fn parse(buf: &str) -> (DeckMeta, usize) {
+    let mut meta = DeckMeta::default();
+    let mut offset = 0;
+
+    for line in buf.split('\n') {
+        let end = offset + line.len() + 1;
+        let line = line.trim_end_matches('\r');
+
+        if line.trim().is_empty() {
+            if !meta.has_known() {
+                return (DeckMeta::default(), 0);
+            }
+            return (meta, end.min(buf.len()));
+        }
+
+        match key_value(line) {
+            Some((key, value)) => meta.insert(&key.to_lowercase(), value),
+            None => return (DeckMeta::default(), 0),
+        }
+        offset = end;
+    }
+
+    // The whole file is metadata, no slides.
+    if !meta.has_known() {
+        return (DeckMeta::default(), 0);
+    }
+    (meta, buf.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_block() {
+        let deck = "title: Talk\nAuthor: me\nvenue: here\n\n# Slide\n";
+        let (meta, offset) = parse(deck);
+        assert_eq!(meta.title.as_ref().unwrap(), "Talk");
+        assert_eq!(meta.get("author"), Some("me"));
+        assert_eq!(meta.get("venue"), Some("here"));
+        assert_eq!(&deck[offset..], "# Slide\n");
+    }
+
+    #[test]
+    fn only_metadata() {
+        let (meta, offset) = parse("date: today");
+        assert_eq!(meta.get("date"), Some("today"));
+        assert_eq!(offset, 11);
+    }
+
+    #[test]
+    fn no_metadata() {
+        let (meta, offset) = parse("# Slide\n\ntitle: not metadata\n");
+        assert!(meta.is_empty());
+        assert_eq!(offset, 0);
+    }
+
+    #[test]
+    fn content_that_looks_like_metadata() {
+        for deck in &["Note: this deck is about things\n\n# Slide\n", "http://example.com\n\n# Slide\n"] {
+            let (meta, offset) = parse(deck);
+            assert!(meta.is_empty());
+            assert_eq!(offset, 0);
+        }
+    }
+}
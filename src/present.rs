@@ -3,8 +3,9 @@ use pulldown_cmark::{Parser, Tag};
 use pulldown_cmark::Event::{self, End, Html, InlineHtml, Start, Text};
 use pulldown_cmark::Event::{FootnoteReference, HardBreak, SoftBreak};
 
+use std::borrow::Cow;
 use std::io::Result;
-use view::View;
+use view::{Align, ImageAttributes, Size, View};
 
 pub trait Present {
     fn present(&self, view: &mut View) -> Result<()>;
@@ -18,14 +19,90 @@ impl<'a> Present for Slide<'a> {
             info!("{:?}", element);
         }
 
-        let parser = Parser::new(&content);
-        for element in parser {
-            view.present(&element)?;
+        let mut events: Vec<Event> = Parser::new(&content).collect();
+        for i in 0..events.len() {
+            let (event, rest) = events[i..].split_first_mut().unwrap();
+            if let Start(Tag::Image(ref path, _)) = *event {
+                let attributes = take_image_attributes(rest);
+                view.start_image(path, &attributes)?;
+            } else {
+                if let Text(ref text) = *event {
+                    view.set_centered(ends_centered(text, rest));
+                }
+                view.present(event)?;
+            }
         }
         Ok(())
     }
 }
 
+/// Whether the line that starts with `text` and goes on in `events` ends
+/// with a `<-` marker.
+fn ends_centered(text: &str, events: &[Event]) -> bool {
+    let mut line = String::from(text);
+    for event in events {
+        match *event {
+            Text(ref text) => line.push_str(text),
+            Start(Tag::Emphasis) | End(Tag::Emphasis) | Start(Tag::Strong) | End(Tag::Strong) |
+            Start(Tag::Code) | End(Tag::Code) | Start(Tag::Link(..)) | End(Tag::Link(..)) => {}
+            _ => break,
+        }
+    }
+    line.trim_end().ends_with("<-")
+}
+
+/// Remove the `{...}` attributes following the image that `events` starts
+/// in, and return them.
+fn take_image_attributes(events: &mut [Event]) -> ImageAttributes {
+    let end = match events.iter().position(|e| matches!(*e, End(Tag::Image(..)))) {
+        Some(end) => end,
+        None => return ImageAttributes::default(),
+    };
+
+    if let Some(&mut Text(ref mut text)) = events.get_mut(end + 1) {
+        if let Some((attributes, rest)) = image_attributes(text) {
+            *text = Cow::from(String::from(rest));
+            return attributes;
+        }
+    }
+    ImageAttributes::default()
+}
+
+/// Parse `{width=50% height=10 align=center}` at the start of `text`, and
+/// return it with the rest of the text.
+fn image_attributes(text: &str) -> Option<(ImageAttributes, &str)> {
+    if !text.starts_with('{') {
+        return None;
+    }
+    let end = text.find('}')?;
+
+    let mut attributes = ImageAttributes::default();
+    for attribute in text[1..end].split_whitespace() {
+        let (key, value) = attribute.split_once('=')?;
+        match key {
+            "width" => attributes.width = Some(size(value)?),
+            "height" => attributes.height = Some(size(value)?),
+            "align" => {
+                attributes.align = Some(match value {
+                    "left" => Align::Left,
+                    "center" => Align::Center,
+                    "right" => Align::Right,
+                    _ => return None,
+                })
+            }
+            _ => warn!("unknown image attribute {}", key),
+        }
+    }
+    Some((attributes, &text[end + 1..]))
+}
+
+fn size(value: &str) -> Option<Size> {
+    match value.strip_suffix('%') {
+        Some(percent) => percent.parse().ok().map(Size::Percent),
+        None => value.parse().ok().map(Size::Cells),
+    }
+}
+
 impl<'a> Present for Event<'a> {
     fn present(&self, view: &mut View) -> Result<()> {
         match *self {
@@ -48,12 +125,16 @@ impl<'a> Present for Event<'a> {
             End(Tag::List(_)) => view.end_list(),
             Start(Tag::Item) => view.start_item(),
             End(Tag::Item) => view.end_item(),
-            Start(Tag::Image(ref path, _)) => view.start_image(path),
+            Start(Tag::Image(ref path, _)) => {
+                view.start_image(path, &ImageAttributes::default())
+            }
             End(Tag::Image(ref path, _)) => view.end_image(path),
             Start(_) | End(_) => Ok(()),
             Text(ref text) => view.show_text(text),
+            // Pause markers (`<br>`) are handled when splitting, other html
+            // is not rendered.
             Html(ref _html) |
-            InlineHtml(ref _html) => unimplemented!{},
+            InlineHtml(ref _html) => Ok(()),
             FootnoteReference(ref _ref) => unimplemented!{},
             HardBreak => {
                 view.newline()?;
@@ -62,3 +143,37 @@ impl<'a> Present for Event<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes() {
+        let (attributes, rest) = image_attributes("{width=50% height=10 align=center} text").unwrap();
+        let expected = ImageAttributes {
+            width: Some(Size::Percent(50)),
+            height: Some(Size::Cells(10)),
+            align: Some(Align::Center),
+        };
+        assert_eq!(attributes, expected);
+        assert_eq!(rest, " text");
+    }
+
+    #[test]
+    fn unknown_attributes_are_skipped() {
+        let (attributes, rest) = image_attributes("{border=1 align=right}").unwrap();
+        assert_eq!(attributes.align, Some(Align::Right));
+        assert_eq!(attributes.width, None);
+        assert_eq!(rest, "");
+    }
+
+    #[test]
+    fn no_attributes() {
+        assert!(image_attributes("text {width=5}").is_none());
+        assert!(image_attributes("{width=5").is_none());
+        assert!(image_attributes("{width}").is_none());
+        assert!(image_attributes("{width=wide}").is_none());
+        assert!(image_attributes("{align=top}").is_none());
+    }
+}
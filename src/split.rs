@@ -1,11 +1,15 @@
 //! Split a full markdown file into each slides
 
-use image::retrieve_image;
+use asset::AssetStore;
+use error::Error;
+use get::Base;
 use pulldown_cmark::{Event, Parser, Tag};
 use std::borrow::Cow;
 
 pub struct Split<'a> {
     buf: &'a str,
+    base: Base,
+    assets: AssetStore,
     parser: Parser<'a>,
     start_offset: usize,
     end_offset: usize,
@@ -16,7 +20,8 @@ pub struct Split<'a> {
 impl<'a> Split<'a> {}
 
 impl<'a> Iterator for Split<'a> {
-    type Item = (usize, Cow<'a, str>);
+    /// Offset, content and title of a slide.
+    type Item = Result<(usize, Cow<'a, str>, Option<String>), Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
@@ -28,9 +33,9 @@ impl<'a> Iterator for Split<'a> {
                     } else {
                         let end = self.buf.len();
                         let content = Cow::from(&self.buf[self.start_offset..end]);
-                        let ret = (self.start_offset, content);
+                        let ret = (self.start_offset, content.clone(), title(&content));
                         self.start_offset = end;
-                        return Some(ret);
+                        return Some(Ok(ret));
                     }
                 }
             };
@@ -38,28 +43,32 @@ impl<'a> Iterator for Split<'a> {
             match event {
                 Event::Start(Tag::Rule) => {
                     if let Some(Event::End(Tag::Rule)) = self.parser.next() {
-                        let s = &self.buf[self.start_offset..self.end_offset];
+                        // Nothing between two rules is an empty slide
+                        let end = self.end_offset.max(self.start_offset);
+                        let s = &self.buf[self.start_offset..end];
                         let content = Cow::from(s);
-                        let ret = (self.start_offset, content);
+                        let ret = (self.start_offset, content, title(s));
                         self.start_offset = self.parser.get_offset();
 
                         // One page ready
                         self.first_page = false;
-                        return Some(ret);
+                        return Some(Ok(ret));
                     } else {
-                        // Tag mismatch, error
-                        error!("Surprising markdown file/parser for Tag::Rule");
-                        ::std::process::exit(-1);
+                        // Tag mismatch, stop splitting
+                        self.start_offset = self.buf.len();
+                        let offset = self.parser.get_offset();
+                        let why = format!("unexpected markdown at byte {} after a rule", offset);
+                        return Some(Err(Error::Parse(why)));
                     }
                 }
                 Event::Start(Tag::Image(path, _)) => {
-                    let path = String::from(path);
+                    let location = self.base.resolve(&path);
                     if self.first_page {
                         // synchronous read for first page
-                        retrieve_image(path);
+                        self.assets.load(&path, &location);
                     } else {
                         // asynchronous read for the rest of the slides
-                        ::std::thread::spawn(move || retrieve_image(path));
+                        self.assets.request(&path, &location);
                     }
                 }
                 _ => {}
@@ -69,12 +78,197 @@ impl<'a> Iterator for Split<'a> {
     }
 }
 
-pub fn split(buf: &str) -> Split {
+/// Split `buf`, loading the images it refers to relative to `base` into
+/// `assets`.
+pub fn split<'a>(buf: &'a str, base: &Base, assets: &AssetStore) -> Split<'a> {
     Split {
-        buf: buf,
+        buf,
+        base: base.clone(),
+        assets: assets.clone(),
         parser: Parser::new(buf),
         start_offset: 0,
         end_offset: 0,
         first_page: true,
     }
 }
+
+/// The text of the first header in a slide, without alignment markers.
+fn title(content: &str) -> Option<String> {
+    let content = align_headers(Cow::from(content));
+    let mut title: Option<String> = None;
+    for event in Parser::new(&content) {
+        match event {
+            Event::Start(Tag::Header(_)) => title = Some(String::new()),
+            Event::Text(text) => {
+                if let Some(ref mut title) = title {
+                    title.push_str(&text);
+                }
+            }
+            Event::End(Tag::Header(_)) => break,
+            _ => {}
+        }
+    }
+
+    title
+        .map(|title| {
+            let title = title.trim();
+            let title = title.trim_start_matches("->").trim_end_matches("<-");
+            String::from(title.trim())
+        })
+        .and_then(|title| if title.is_empty() { None } else { Some(title) })
+}
+
+/// The name given to a slide with a `<!-- id: name -->` comment.
+pub fn anchor(content: &str) -> Option<String> {
+    content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| line.starts_with("<!--") && line.ends_with("-->"))
+        .map(|line| line[4..line.len() - 3].trim())
+        .filter(|comment| comment.starts_with("id:"))
+        .map(|comment| String::from(comment[3..].trim()))
+        .next()
+}
+
+/// Split a `-> # title <-` line into the `#`s and the rest, if it's a header
+/// with an alignment marker.
+fn aligned_header(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start();
+    if !line.starts_with("->") {
+        return None;
+    }
+    let rest = line[2..].trim_start();
+    let level = rest.len() - rest.trim_start_matches('#').len();
+    if level == 0 || level > 6 || !rest[level..].starts_with(' ') {
+        return None;
+    }
+    Some((&rest[..level], rest[level..].trim_start()))
+}
+
+/// Move the `->` marker of a header behind the `#`s so that the parser still
+/// sees a header: `-> # title <-` becomes `# -> title <-`. The markers are
+/// then handled by the view like those of a paragraph.
+pub fn align_headers<'a>(content: Cow<'a, str>) -> Cow<'a, str> {
+    if !content.lines().any(|line| aligned_header(line).is_some()) {
+        return content;
+    }
+
+    let mut out = String::with_capacity(content.len());
+    for (i, line) in content.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        match aligned_header(line) {
+            Some((level, title)) => {
+                out.push_str(level);
+                out.push_str(" -> ");
+                out.push_str(title);
+            }
+            None => out.push_str(line),
+        }
+    }
+    Cow::from(out)
+}
+
+/// A line consisting of only `<br>` or `^` stops the output at that position.
+fn is_pause(line: &str) -> bool {
+    matches!(line.trim(), "^" | "<br>" | "<br/>" | "<br />")
+}
+
+/// Byte ranges of the code blocks in `content`.
+fn code_blocks(content: &str) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    let mut parser = Parser::new(content);
+    let mut start = None;
+    loop {
+        let offset = parser.get_offset();
+        match parser.next() {
+            Some(Event::Start(Tag::CodeBlock(_))) => start = Some(offset),
+            Some(Event::End(Tag::CodeBlock(_))) => {
+                if let Some(start) = start.take() {
+                    blocks.push((start, parser.get_offset()));
+                }
+            }
+            Some(_) => {}
+            None => return blocks,
+        }
+    }
+}
+
+/// Remove the pause markers from a slide.
+///
+/// Returns the slide without markers together with the offsets (into the
+/// returned content) where each marker was. Markers inside code blocks, fenced
+/// or indented, are left alone.
+pub fn pauses<'a>(content: Cow<'a, str>) -> (Cow<'a, str>, Vec<usize>) {
+    let code = code_blocks(&content);
+    let mut stripped = String::with_capacity(content.len());
+    let mut stops: Vec<usize> = Vec::new();
+    {
+        let mut start = 0;
+        let mut lines = content.split('\n').peekable();
+        while let Some(line) = lines.next() {
+            let in_code = code.iter().any(|&(from, to)| from <= start && start < to);
+            start += line.len() + 1;
+            if !in_code && is_pause(line) {
+                stops.push(stripped.len());
+                continue;
+            }
+
+            stripped.push_str(line);
+            if lines.peek().is_some() {
+                stripped.push('\n');
+            }
+        }
+    }
+
+    if stops.is_empty() {
+        return (content, stops);
+    }
+
+    // A marker at the very end (or several in a row) doesn't add a step.
+    let len = stripped.len();
+    stops.retain(|&stop| stop < len);
+    stops.dedup();
+    (Cow::from(stripped), stops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(content: &str) -> (String, Vec<usize>) {
+        let (content, stops) = pauses(Cow::from(content));
+        (content.into_owned(), stops)
+    }
+
+    #[test]
+    fn pauses_in_lists() {
+        assert_eq!(strip("- a\n  ^\n- b"), (String::from("- a\n- b"), vec![4]));
+    }
+
+    #[test]
+    fn pauses_between_paragraphs() {
+        assert_eq!(strip("a\n\n<br>\n\nb"), (String::from("a\n\n\nb"), vec![3]));
+    }
+
+    #[test]
+    fn no_pause_at_the_end() {
+        assert_eq!(strip("a\n^"), (String::from("a\n"), vec![]));
+    }
+
+    #[test]
+    fn aligned_headers() {
+        let content = align_headers(Cow::from("-> ## Title <-\n\n-> text <-\n->#no"));
+        assert_eq!(content, "## -> Title <-\n\n-> text <-\n->#no");
+    }
+
+    #[test]
+    fn no_pauses_in_code_blocks() {
+        let fenced = "```\n^\n```\n\nb";
+        assert_eq!(strip(fenced), (String::from(fenced), vec![]));
+
+        let indented = "error:\n\n    let x: u32 = \"a\";\n                 ^\n\nb";
+        assert_eq!(strip(indented), (String::from(indented), vec![]));
+    }
+}
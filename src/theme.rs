@@ -0,0 +1,100 @@
+//! Colors of the viewer, for dark and light terminals
+
+use buffer::{Color, Style};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// Headers, also of the table of contents.
+    pub header: Style,
+    /// Inline code and code blocks.
+    pub code: Style,
+    /// The bar left of block quotes.
+    pub quote: Style,
+    /// The page counter.
+    pub page_num: Style,
+    /// Less important text: the deck's metadata and image placeholders.
+    pub dim: Style,
+    /// Errors in the status line.
+    pub error: Style,
+}
+
+impl Theme {
+    pub fn dark() -> Theme {
+        Theme {
+            header: Style {
+                fg: Color::LightCyan,
+                underline: true,
+                ..Style::default()
+            },
+            code: Style {
+                fg: Color::Black,
+                bg: Color::LightWhite,
+                ..Style::default()
+            },
+            quote: Style {
+                bg: Color::LightWhite,
+                ..Style::default()
+            },
+            page_num: Style {
+                fg: Color::LightRed,
+                ..Style::default()
+            },
+            dim: Style {
+                fg: Color::LightBlack,
+                ..Style::default()
+            },
+            error: Style {
+                fg: Color::LightRed,
+                bold: true,
+                ..Style::default()
+            },
+        }
+    }
+
+    pub fn light() -> Theme {
+        Theme {
+            header: Style {
+                fg: Color::Ansi(25),
+                underline: true,
+                ..Style::default()
+            },
+            code: Style {
+                fg: Color::Ansi(255),
+                bg: Color::Ansi(238),
+                ..Style::default()
+            },
+            quote: Style {
+                bg: Color::Ansi(246),
+                ..Style::default()
+            },
+            page_num: Style {
+                fg: Color::Ansi(124),
+                ..Style::default()
+            },
+            dim: Style {
+                fg: Color::Ansi(243),
+                ..Style::default()
+            },
+            error: Style {
+                fg: Color::Ansi(160),
+                bold: true,
+                ..Style::default()
+            },
+        }
+    }
+
+    /// The theme called `name`, `dark` or `light`.
+    pub fn named(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(Theme::dark()),
+            "light" => Some(Theme::light()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::dark()
+    }
+}
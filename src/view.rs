@@ -1,15 +1,28 @@
-use image::inline_image;
+use asset::{Asset, AssetStore};
+use backend::{Backend, Termion};
+use buffer::{Buffer, Style};
+use error::Error;
+use image::{self, Placement, Protocol, inline_image};
 use present::Present;
 use std::borrow::Cow;
-use std::cmp::min;
-use std::io::{Result, Stdout, Write, stdout};
-use termion::{self, color, cursor, style};
-
-#[derive(Debug)]
+use std::cmp::{max, min};
+use std::io::{self, Result};
+use theme::Theme;
+use unicode_width::UnicodeWidthChar;
+
+/// Renders slides into a `Buffer`, which is shown by a `Backend` on `flush`.
+///
+/// Positions given to and returned by the view are 1-based, like those of
+/// `termion::cursor::Goto`.
 pub struct View {
+    backend: Box<dyn Backend>,
+    buffer: Buffer,
+
+    /// Where images are looked up.
+    assets: AssetStore,
+
     term_width: u16,
     term_height: u16,
-    stdout: Stdout,
 
     width: u16,
     left_margin: u16,
@@ -18,6 +31,60 @@ pub struct View {
     bottom_margin: u16,
 
     ctx: Context,
+    styles: Styles,
+    theme: Theme,
+
+    /// Cursor in the buffer (0-based).
+    x: u16,
+    y: u16,
+
+    /// Alignment of the current line, applied when it ends.
+    align: Align,
+    /// Whether the line of the next text ends with `<-`, so that it's
+    /// centered from the start and not only once the marker is seen.
+    centered: bool,
+}
+
+/// Horizontal alignment of a line, set with `->` and `<-` markers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Size and alignment of an image, given as `{width=50% height=10
+/// align=center}` right after it.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ImageAttributes {
+    pub width: Option<Size>,
+    pub height: Option<Size>,
+    pub align: Option<Align>,
+}
+
+/// A length in cells or relative to the slide.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Size {
+    Cells(u16),
+    Percent(u16),
+}
+
+impl Size {
+    fn cells(&self, total: u16) -> u16 {
+        match *self {
+            Size::Cells(n) => n,
+            Size::Percent(p) => (total as u32 * p as u32 / 100) as u16,
+        }
+    }
+}
+
+/// Inline styles that are active at the cursor.
+#[derive(Debug, Default, Clone, Copy)]
+struct Styles {
+    bold: bool,
+    italic: bool,
+    code: bool,
+    header: bool,
 }
 
 #[derive(Debug)]
@@ -42,67 +109,293 @@ enum ListState {
 }
 
 impl View {
-    pub fn new() -> Result<Self> {
-        let (term_width, term_height) = termion::terminal_size()?;
-        let width = min(80, term_width - 4);
-        let bottom_margin = term_height / 10;
+    /// A view of the terminal, on the alternate screen if `alternate_screen`.
+    pub fn new(alternate_screen: bool) -> Result<Self> {
+        View::with_backend(Box::new(Termion::new(alternate_screen)))
+    }
 
-        let view = View {
-            term_width: term_width,
-            term_height: term_height,
-            stdout: stdout(),
+    pub fn with_backend(mut backend: Box<dyn Backend>) -> Result<Self> {
+        let (term_width, term_height) = backend.size()?;
+        backend.enter()?;
+        let mut view = View {
+            backend,
+            buffer: Buffer::new(term_width, term_height),
+            assets: AssetStore::new(),
 
-            width: width,
+            term_width: 0,
+            term_height: 0,
 
-            left_margin: (term_width - width) / 2 - 1,
-            right_margin: (term_width - width) / 2,
+            width: 0,
+            left_margin: 0,
+            right_margin: 0,
             top_margin: 2,
-            bottom_margin: bottom_margin,
+            bottom_margin: 0,
 
             ctx: Context::Default,
+            styles: Styles::default(),
+            theme: Theme::default(),
+
+            x: 0,
+            y: 0,
+            align: Align::Left,
+            centered: false,
         };
+        view.layout(term_width, term_height);
         Ok(view)
     }
 
+    /// Show the images of the deck that loaded `assets`.
+    pub fn set_assets(&mut self, assets: &AssetStore) {
+        self.assets = assets.clone();
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Draw with the colors of `theme` from the next frame on.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.invalidate();
+    }
+
     pub fn update(&mut self) -> Result<()> {
-        let (term_width, term_height) = termion::terminal_size()?;
-        let width = min(80, term_width - 4);
+        let (term_width, term_height) = self.backend.size()?;
+        self.layout(term_width, term_height);
+        self.buffer.resize(term_width, term_height);
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Redraw the whole screen on the next `flush`.
+    pub fn invalidate(&mut self) {
+        self.backend.invalidate();
+    }
+
+    fn layout(&mut self, term_width: u16, term_height: u16) {
+        // Tiny terminals get no content width rather than an overflow
+        let width = min(80, term_width.saturating_sub(4));
 
         self.term_width = term_width;
         self.term_height = term_height;
         self.width = width;
-        self.left_margin = (term_width - width) / 2 - 1;
+        self.left_margin = max(1, ((term_width - width) / 2).saturating_sub(1));
         self.right_margin = (term_width - width) / 2;
         self.bottom_margin = term_height / 10;
-        Ok(())
     }
 
+    /// Start a new frame.
     pub fn clear(&mut self) -> Result<()> {
-        write!(
-            self.stdout,
-            "{}{}",
-            termion::clear::All,
-            cursor::Goto(self.left_margin, self.top_margin)
-        )
+        self.buffer.clear();
+        self.x = self.left_margin - 1;
+        self.y = self.top_margin - 1;
+        self.align = Align::Left;
+        // After a slide that couldn't be shown
+        self.ctx = Context::Default;
+        self.styles = Styles::default();
+        Ok(())
     }
 
     pub fn quit(&mut self) -> Result<()> {
         self.reset()?;
         self.show_cursor()?;
-        self.flush()
+        self.backend.leave()
     }
 
     pub fn reset(&mut self) -> Result<()> {
-        write!(self.stdout, "{}{}", termion::clear::All, cursor::Goto(1, 1))
+        self.backend.reset()
     }
 
     pub fn info(&mut self) -> Result<()> {
-        write!(self.stdout, "Your terminal is ")?;
-        write!(self.stdout, "{}x{}", self.term_width, self.term_height)
+        let info = format!("Your terminal is {}x{}", self.term_width, self.term_height);
+        self.print(&info)
     }
 
     pub fn newline(&mut self) -> Result<()> {
-        write!(self.stdout, "\n{}", cursor::Right(self.left_margin - 1))
+        self.end_line();
+        self.x = self.left_margin - 1;
+        self.y += 1;
+        Ok(())
+    }
+
+    /// Align the current line within `width`.
+    fn end_line(&mut self) {
+        let origin = self.left_margin - 1;
+        let pad = match self.align {
+            Align::Left => 0,
+            align => {
+                // Trailing spaces don't count
+                let mut end = self.x;
+                while end > origin &&
+                    self.buffer.get(end - 1, self.y).map(|c| &c.symbol[..]) == Some(" ")
+                {
+                    end -= 1;
+                }
+                let room = self.width.saturating_sub(end - origin);
+                if align == Align::Center { room / 2 } else { room }
+            }
+        };
+        self.buffer.shift_right(self.y, origin, self.x, pad);
+        self.align = Align::Left;
+    }
+
+    /// Write `text` at the cursor with the current style.
+    pub fn print(&mut self, text: &str) -> Result<()> {
+        let style = self.style();
+        for c in text.chars() {
+            if c == '\t' {
+                for _ in 0..4 {
+                    self.x += self.buffer.set(self.x, self.y, ' ', style);
+                }
+                continue;
+            }
+            self.x += self.buffer.set(self.x, self.y, c, style);
+        }
+        Ok(())
+    }
+
+    /// Write `text` at `(x, y)` with `style`, leaving the cursor alone.
+    pub fn print_at(&mut self, x: u16, y: u16, text: &str, style: Style) -> Result<()> {
+        let mut x = x.saturating_sub(1);
+        let y = y.saturating_sub(1);
+        for c in text.chars() {
+            x += self.buffer.set(x, y, c, style);
+        }
+        Ok(())
+    }
+
+    /// Pass `data` through to the terminal at the cursor.
+    pub fn raw(&mut self, data: Vec<u8>) -> Result<()> {
+        self.buffer.put_raw(self.x, self.y, data);
+        Ok(())
+    }
+
+    /// The style of text written at the cursor.
+    fn style(&self) -> Style {
+        let mut style = Style::default();
+        if let Context::CodeBlock(_) = self.ctx {
+            style = self.theme.code;
+        }
+        if self.styles.header {
+            style = self.theme.header;
+        }
+        if self.styles.code {
+            style = self.theme.code;
+        }
+        style.bold = self.styles.bold;
+        style.italic = self.styles.italic;
+        style
+    }
+
+    /// Number of columns used by the current line.
+    fn line_width(&self) -> usize {
+        (self.x + 1 - self.left_margin) as usize
+    }
+
+    /// Number of columns a continuation line is indented by in the current
+    /// context.
+    fn indent_width(&self) -> usize {
+        match self.ctx {
+            Context::Quote => 2,
+            Context::List(i, _) => 3 * (i + 1),
+            _ => 0,
+        }
+    }
+
+    fn indent(&mut self) -> Result<()> {
+        match self.ctx {
+            Context::Quote => self.quote_bar(),
+            Context::List(i, _) => {
+                (0..i + 1).map(|_| self.print("   ")).count();
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn quote_bar(&mut self) -> Result<()> {
+        let bar = self.theme.quote;
+        self.x += self.buffer.set(self.x, self.y, ' ', bar);
+        self.x += self.buffer.set(self.x, self.y, ' ', Style::default());
+        Ok(())
+    }
+
+    /// Continue on the next line, keeping alignment and indentation.
+    fn wrap(&mut self) -> Result<()> {
+        let align = self.align;
+        self.newline()?;
+        self.align = align;
+        self.indent()
+    }
+
+    /// Write `text`, breaking lines on whitespace so that they fit in `width`.
+    /// Words longer than a line are broken anywhere.
+    fn show_wrapped(&mut self, text: &str) -> Result<()> {
+        let width = self.width as usize;
+        for (i, word) in text.split(' ').enumerate() {
+            let sep = if i == 0 { "" } else { " " };
+            let word_width = visible_width(word);
+            let used = self.line_width();
+
+            if used + sep.len() + word_width <= width {
+                self.print(sep)?;
+                self.print(word)?;
+                continue;
+            }
+
+            if used > self.indent_width() {
+                self.wrap()?;
+            } else {
+                self.print(sep)?;
+            }
+
+            if self.line_width() + word_width <= width {
+                self.print(word)?;
+                continue;
+            }
+            for c in word.chars() {
+                if self.line_width() + c.width().unwrap_or(0) > width {
+                    self.wrap()?;
+                }
+                self.print(&c.to_string())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Strip the `->` and `<-` markers from `text` and align the current line
+    /// accordingly: `-> text` is right-aligned, `-> text <-` is centered.
+    fn take_align_markers<'b>(&mut self, text: &'b str) -> &'b str {
+        let mut text = text;
+
+        if self.line_width() == 0 && text.trim_start().starts_with("->") {
+            text = text.trim_start()[2..].trim_start();
+            // Lines wrapped before the `<-` is seen are aligned already
+            self.align = if self.centered { Align::Center } else { Align::Right };
+        }
+
+        if self.align != Align::Left && text.trim_end().ends_with("<-") {
+            let trimmed = text.trim_end();
+            text = trimmed[..trimmed.len() - 2].trim_end();
+            self.align = Align::Center;
+
+            // The parser may hand us the marker on its own, after the space.
+            if text.is_empty() {
+                let origin = self.left_margin - 1;
+                while self.x > origin &&
+                    self.buffer.get(self.x - 1, self.y).map(|c| &c.symbol[..]) == Some(" ")
+                {
+                    self.x -= 1;
+                    self.buffer.set(self.x, self.y, ' ', Style::default());
+                }
+            }
+        }
+        text
+    }
+
+    /// Tell whether the line of the text shown next ends with a `<-` marker.
+    pub fn set_centered(&mut self, centered: bool) {
+        self.centered = centered;
     }
 
     pub fn right_bottom(&self) -> (u16, u16) {
@@ -111,24 +404,54 @@ impl View {
         (right, bottom)
     }
 
+    pub fn left_top(&self) -> (u16, u16) {
+        (self.left_margin, self.top_margin)
+    }
+
+    pub fn left_bottom(&self) -> (u16, u16) {
+        let bottom = self.term_height - self.bottom_margin;
+        (self.left_margin, bottom)
+    }
+
     pub fn width(&self) -> u16 {
         self.width
     }
 
+    /// Number of rows between the top and bottom margin.
+    pub fn height(&self) -> u16 {
+        self.term_height
+            .saturating_sub(self.top_margin)
+            .saturating_sub(self.bottom_margin)
+    }
+
+    /// Size of the terminal in columns and rows.
+    pub fn term_size(&self) -> (u16, u16) {
+        (self.term_width, self.term_height)
+    }
+
     pub fn present<P: Present>(&mut self, p: &P) -> Result<()> {
         p.present(self)
     }
 
-    pub fn show_text<'a>(&mut self, text: &Cow<'a, str>) -> Result<()> {
+    /// Show the rendered frame.
+    pub fn flush(&mut self) -> Result<()> {
+        self.end_line();
+        self.backend.draw(&self.buffer)
+    }
+
+    pub fn show_text(&mut self, text: &str) -> Result<()> {
         match self.ctx {
             Context::Default |
-            Context::Image(false) => self.present(text),
+            Context::Image(false) => {
+                let text = self.take_align_markers(text);
+                self.show_wrapped(text)
+            }
             Context::CodeBlock(i) => {
                 if text.ends_with('\n') {
-                    let content = text.trim_right_matches('\n');
+                    let content = text.trim_end_matches('\n');
 
                     let cols = self.width() as usize;
-                    let to_fill = cols - content.len() - i;
+                    let to_fill = cols.saturating_sub(visible_width(content) + i);
                     let fill = (0..to_fill).map(|_| ' ').collect::<String>();
 
                     self.present(&content)?;
@@ -136,19 +459,16 @@ impl View {
                     self.newline()?;
                     self.ctx = Context::CodeBlock(0);
                 } else {
-                    self.present(text)?;
-                    self.ctx = Context::CodeBlock(i + text.len());
+                    self.present(&text)?;
+                    self.ctx = Context::CodeBlock(i + visible_width(text));
                 }
                 Ok(())
             }
             Context::Quote => {
-                write!(
-                    self,
-                    "{} {} ",
-                    color::Bg(color::LightWhite),
-                    color::Bg(color::Reset)
-                )?;
-                self.present(text)
+                if self.line_width() == 0 {
+                    self.quote_bar()?;
+                }
+                self.show_wrapped(text)
             }
             Context::List(i, list_state) => {
                 match list_state {
@@ -156,63 +476,64 @@ impl View {
                         self.ctx = Context::List(i, ListState::ItemContinue);
                     }
                     ListState::ItemContinue => {
-                        write!(self, "   ")?;
-                        (0..i).map(|_| write!(self, "   ")).count();
+                        self.print("   ")?;
+                        (0..i).map(|_| self.print("   ")).count();
                     }
                     ListState::JustEnd => {}
                 }
-                self.present(text)
+                self.show_wrapped(text)
             }
             Context::Image(true) => Ok(()),
         }
     }
 
     pub fn hide_cursor(&mut self) -> Result<()> {
-        write!(self, "{}", cursor::Hide)
+        self.backend.hide_cursor()
     }
 
     pub fn show_cursor(&mut self) -> Result<()> {
-        write!(self, "{}", cursor::Show)
+        self.backend.show_cursor()
     }
 
     pub fn start_code(&mut self) -> Result<()> {
-        write!(self, "{}", color::Bg(color::LightWhite))?;
-        write!(self, "{}", color::Fg(color::Black))
+        self.styles.code = true;
+        Ok(())
     }
 
     pub fn end_code(&mut self) -> Result<()> {
-        write!(self, "{}", color::Fg(color::Reset))?;
-        write!(self, "{}", color::Bg(color::Reset))
+        self.styles.code = false;
+        Ok(())
     }
 
     pub fn start_codeblock(&mut self) -> Result<()> {
-        self.ctx = Context::CodeBlock(0);
         self.newline()?;
-        write!(self, "{}", color::Bg(color::LightWhite))?;
-        write!(self, "{}", color::Fg(color::Black))
+        self.ctx = Context::CodeBlock(0);
+        Ok(())
     }
 
     pub fn end_codeblock(&mut self) -> Result<()> {
         self.ctx = Context::Default;
-        self.newline()?;
-        write!(self, "{}", color::Fg(color::Reset))?;
-        write!(self, "{}", color::Bg(color::Reset))
+        self.newline()
     }
 
     pub fn start_italic(&mut self) -> Result<()> {
-        write!(self, "{}", style::Italic)
+        self.styles.italic = true;
+        Ok(())
     }
 
     pub fn end_italic(&mut self) -> Result<()> {
-        write!(self, "{}", style::NoItalic)
+        self.styles.italic = false;
+        Ok(())
     }
 
     pub fn start_bold(&mut self) -> Result<()> {
-        write!(self, "{}", style::Bold)
+        self.styles.bold = true;
+        Ok(())
     }
 
     pub fn end_bold(&mut self) -> Result<()> {
-        write!(self, "{}", style::Reset)
+        self.styles.bold = false;
+        Ok(())
     }
 
     pub fn start_paragraph(&mut self) -> Result<()> {
@@ -244,7 +565,7 @@ impl View {
             Context::List(i, _) => {
                 self.ctx = Context::List(i + 1, ListState::ParagraphFirst);
             }
-            _ => unreachable!{},
+            _ => return Err(unsupported("a list in a block quote")),
         }
         self.newline()
     }
@@ -257,10 +578,7 @@ impl View {
             Context::List(i, state) => {
                 self.ctx = Context::List(i - 1, state);
             }
-            ref ctx => {
-                error!("{:?}", &ctx);
-                unreachable!{}
-            }
+            _ => return Err(unsupported("a code block or block quote in a list")),
         }
         self.newline()
     }
@@ -268,11 +586,11 @@ impl View {
     pub fn start_item(&mut self) -> Result<()> {
         match self.ctx {
             Context::List(i, _) => {
-                (0..i).map(|_| write!(self, "   ")).count();
-                write!(self, "+- ")?;
+                (0..i).map(|_| self.print("   ")).count();
+                self.print("+- ")?;
                 self.ctx = Context::List(i, ListState::ParagraphFirst);
             }
-            _ => unimplemented!{},
+            _ => return Err(unsupported("a code block or block quote in a list")),
         }
         Ok(())
     }
@@ -284,65 +602,355 @@ impl View {
                 self.ctx = Context::List(level, ListState::JustEnd);
                 self.newline()
             }
-            _ => {
-                unreachable!{}
-            }
+            _ => Err(unsupported("a code block or block quote in a list")),
         }
     }
 
     pub fn start_header(&mut self, _level: i32) -> Result<()> {
         self.newline()?;
-        write!(self, "{}{}", color::Fg(color::LightCyan), style::Underline)
+        self.styles.header = true;
+        Ok(())
     }
 
     pub fn end_header(&mut self, _level: i32) -> Result<()> {
-        write!(
-            self,
-            "{}{}",
-            style::NoUnderline,
-            color::Fg(color::Reset),
-        )?;
+        self.styles.header = false;
         self.newline()
     }
 
-    pub fn start_image<'a>(&mut self, path: &Cow<'a, str>) -> Result<()> {
-        let path = path.clone().into_owned();
-        match inline_image(self, &path) {
-            Ok(()) => self.ctx = Context::Image(true),
-            Err(_) => self.ctx = Context::Image(false),
+    pub fn start_image(&mut self, path: &str, attributes: &ImageAttributes) -> Result<()> {
+        let path = String::from(path);
+        let top = self.top_margin - 1;
+        let cols = self.width.saturating_sub(self.line_width() as u16);
+        let rows = self.height().saturating_sub(self.y - top);
+        let placement = Placement {
+            cols: Some(attributes.width.map_or(cols, |w| w.cells(self.width)).min(cols)),
+            rows: Some(attributes.height.map_or(rows, |h| h.cells(self.height())).min(rows)),
+            grow: attributes.width.is_some() || attributes.height.is_some(),
+        };
+        let align = attributes.align.unwrap_or(Align::Left);
+
+        // Without a way to show images there's only the alt text
+        if image::protocol().is_none() {
+            self.ctx = Context::Image(false);
+            return self.newline();
+        }
+        self.ctx = Context::Image(true);
+
+        let image = match self.assets.get(&path) {
+            Some(Asset::Loaded(image)) => image,
+            Some(Asset::Pending) => {
+                self.placeholder("loading…", cols, align)?;
+                return self.newline();
+            }
+            Some(Asset::Failed(e)) => {
+                let text = failure(&path, &e, cols.saturating_sub(4) as usize);
+                self.placeholder(&text, cols, align)?;
+                return self.newline();
+            }
+            None => {
+                self.placeholder(&format!("{}: not loaded", path), cols, align)?;
+                return self.newline();
+            }
+        };
+
+        if let Some(Protocol::HalfBlocks { .. }) = image::protocol() {
+            match image::half_blocks(&image, placement) {
+                Ok(cells) => self.half_blocks(&cells, cols, align)?,
+                Err(e) => {
+                    let text = failure(&path, &e, cols.saturating_sub(4) as usize);
+                    self.placeholder(&text, cols, align)?
+                }
+            }
+            return self.newline();
+        }
+
+        let mut data = Vec::new();
+        match inline_image(&mut data, &image, placement) {
+            Ok((width, height)) => {
+                self.x += align_offset(align, cols, width);
+                self.raw(data)?;
+                // Rows below the first are taken by the image
+                self.y += height.saturating_sub(1);
+            }
+            Err(e) => {
+                let text = failure(&path, &e, cols.saturating_sub(4) as usize);
+                self.placeholder(&text, cols, align)?
+            }
         }
         self.newline()
     }
 
-    pub fn end_image<'a>(&mut self, _path: &Cow<'a, str>) -> Result<()> {
-        self.ctx = Context::Default;
+    /// Draw a box with `text` in place of an image that can't be shown (yet),
+    /// aligned within `cols`. The cursor is left on the last row.
+    fn placeholder(&mut self, text: &str, cols: u16, align: Align) -> Result<()> {
+        let style = self.theme.dim;
+        let text = truncate(text, cols.saturating_sub(4) as usize);
+        let inner = visible_width(text) + 2;
+        let rows = [
+            format!("┌{}┐", "─".repeat(inner)),
+            format!("│ {} │", text),
+            format!("└{}┘", "─".repeat(inner)),
+        ];
+
+        let x = self.x + align_offset(align, cols, inner as u16 + 2);
+        for (i, row) in rows.iter().enumerate() {
+            if i > 0 {
+                self.newline()?;
+            }
+            self.x = x;
+            for c in row.chars() {
+                self.x += self.buffer.set(self.x, self.y, c, style);
+            }
+        }
         Ok(())
     }
-}
 
-impl Write for View {
-    fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        self.stdout.write(buf)
+    /// Draw the rows of `cells` from the cursor down, aligned within `cols`,
+    /// leaving the cursor on the last row.
+    fn half_blocks(&mut self, cells: &[Vec<(char, Style)>], cols: u16, align: Align) -> Result<()> {
+        let x = self.x;
+        for (i, row) in cells.iter().enumerate() {
+            if i > 0 {
+                self.newline()?;
+            }
+            self.x = x + align_offset(align, cols, row.len() as u16);
+            for &(c, style) in row {
+                self.x += self.buffer.set(self.x, self.y, c, style);
+            }
+        }
+        Ok(())
     }
-    fn flush(&mut self) -> Result<()> {
-        self.stdout.flush()
+
+    pub fn end_image(&mut self, _path: &str) -> Result<()> {
+        self.ctx = Context::Default;
+        Ok(())
     }
 }
 
 impl Present for String {
     fn present(&self, view: &mut View) -> Result<()> {
-        write!(view, "{}", self)
+        view.print(self)
     }
 }
 
-impl<'a> Present for &'a str {
+impl Present for &str {
     fn present(&self, view: &mut View) -> Result<()> {
-        write!(view, "{}", self)
+        view.print(self)
     }
 }
 
 impl<'a> Present for Cow<'a, str> {
     fn present(&self, view: &mut View) -> Result<()> {
-        write!(view, "{}", self)
+        view.print(self)
+    }
+}
+
+/// Columns to skip to align something `width` wide within `cols`.
+fn align_offset(align: Align, cols: u16, width: u16) -> u16 {
+    match align {
+        Align::Left => 0,
+        Align::Center => cols.saturating_sub(width) / 2,
+        Align::Right => cols.saturating_sub(width),
+    }
+}
+
+/// The text shown for the image at `path` that failed with `e`, in `width`
+/// columns: why it failed, then as much of the path as fits.
+fn failure(path: &str, e: &Error, width: usize) -> String {
+    let why = reason(e);
+    let room = width.saturating_sub(visible_width(&why) + 3);
+    if room < 2 {
+        return why;
+    }
+    format!("{} ({})", why, truncate_start(path, room))
+}
+
+/// Why loading an image failed, in short.
+fn reason(e: &Error) -> String {
+    match *e {
+        // The location is the resolved path, shown by the caller shorter.
+        Error::Fetch(_, ref cause) => reason(cause),
+        Error::Io(ref e) if e.kind() == io::ErrorKind::NotFound => String::from("not found"),
+        ref e => e.to_string(),
+    }
+}
+
+/// The end of `s` that fits in `width` columns, with `…` in front if it's
+/// cut.
+fn truncate_start(s: &str, width: usize) -> Cow<'_, str> {
+    if visible_width(s) <= width {
+        return Cow::from(s);
+    }
+    let mut used = 1;
+    let mut start = s.len();
+    for (i, c) in s.char_indices().rev() {
+        used += c.width().unwrap_or(0);
+        if used > width {
+            break;
+        }
+        start = i;
+    }
+    Cow::from(format!("…{}", &s[start..]))
+}
+
+/// The error for markdown the view can't lay out, `what`.
+fn unsupported(what: &str) -> io::Error {
+    let why = format!("{} isn't supported", what);
+    io::Error::new(io::ErrorKind::InvalidData, why)
+}
+
+/// Number of columns `s` takes on screen, not counting escape sequences.
+/// Wide (East Asian) characters take two columns, combining marks none.
+pub fn visible_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\u{1B}' {
+            width += c.width().unwrap_or(0);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters until a final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: until BEL or ST
+            Some(']') => {
+                for c in chars.by_ref() {
+                    if c == '\u{7}' || c == '\u{9C}' {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    width
+}
+
+/// The longest prefix of `s` that fits in `width` columns.
+pub fn truncate(s: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (i, c) in s.char_indices() {
+        used += c.width().unwrap_or(0);
+        if used > width {
+            return &s[..i];
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend::Headless;
+    use buffer::Style;
+    use deck::Slide;
+
+    /// Render `markdown` on a 30x12 screen, where slides are 26 columns
+    /// wide and start in the first column.
+    fn render(markdown: &str) -> Headless {
+        let headless = Headless::new(30, 12);
+        let mut view = View::with_backend(Box::new(headless.clone())).unwrap();
+        view.clear().unwrap();
+        view.present(&Slide::new((0, Cow::from(markdown)))).unwrap();
+        view.hide_cursor().unwrap();
+        view.flush().unwrap();
+        headless
+    }
+
+    #[test]
+    fn width_without_escapes() {
+        assert_eq!(visible_width("plain"), 5);
+        assert_eq!(visible_width("\u{1B}[1mbold\u{1B}[0m"), 4);
+        assert_eq!(visible_width("\u{1B}[38;5;25mcolor\u{1B}[39m"), 5);
+        assert_eq!(visible_width("\u{1B}]8;;x\u{7}link"), 4);
+    }
+
+    #[test]
+    fn width_of_wide_characters() {
+        assert_eq!(visible_width("中文 x"), 6);
+        assert_eq!(visible_width("\u{1B}[1m日本\u{1B}[0m"), 4);
+        assert_eq!(visible_width("e\u{301}"), 1);
+    }
+
+    #[test]
+    fn truncate_to_columns() {
+        assert_eq!(truncate("abcdef", 4), "abcd");
+        assert_eq!(truncate("中文字", 5), "中文");
+        assert_eq!(truncate("中文字", 6), "中文字");
+        assert_eq!(truncate("ab", 4), "ab");
+    }
+
+    #[test]
+    fn wraps_at_the_slide_width() {
+        let screen = render("one two three four five six seven eight nine");
+        let lines = screen.lines();
+        assert_eq!(lines[2], "one two three four five");
+        assert_eq!(lines[3], "six seven eight nine");
+        assert!(!screen.cursor_visible());
+    }
+
+    #[test]
+    fn centers_between_markers() {
+        let lines = render("-> centered <-").lines();
+        assert_eq!(lines[2], format!("{}centered", " ".repeat(9)));
+    }
+
+    #[test]
+    fn centers_wrapped_lines() {
+        let lines = render("-> one two three four five six seven eight <-").lines();
+        assert_eq!(lines[2], " one two three four five");
+        assert_eq!(lines[3], format!("{}six seven eight", " ".repeat(5)));
+
+        let lines = render("-> one two three four five six seven eight").lines();
+        assert_eq!(lines[2], format!("{}one two three four five", " ".repeat(3)));
+        assert_eq!(lines[3], format!("{}six seven eight", " ".repeat(11)));
+    }
+
+    #[test]
+    fn image_failures() {
+        let not_found = io::Error::from(io::ErrorKind::NotFound);
+        let location = String::from("/home/me/talk/img/a.png");
+        let e = Error::Fetch(location, Box::new(Error::Io(not_found)));
+        assert_eq!(failure("img/a.png", &e, 36), "not found (img/a.png)");
+        assert_eq!(failure("img/a.png", &e, 18), "not found (…a.png)");
+        assert_eq!(failure("img/a.png", &e, 9), "not found");
+
+        let e = Error::Fetch(String::from("https://example.com/a.png"), Box::new(Error::NotCached));
+        assert_eq!(failure("a.png", &e, 60), "not cached, can't fetch it offline (a.png)");
+    }
+
+    #[test]
+    fn unsupported_nesting() {
+        for markdown in &["> - item", "- item\n\n  ```\n  code\n  ```\n- next"] {
+            let mut view = View::with_backend(Box::new(Headless::new(30, 12))).unwrap();
+            view.clear().unwrap();
+            let slide = Slide::new((0, Cow::from(*markdown)));
+            assert!(view.present(&slide).is_err());
+        }
+    }
+
+    #[test]
+    fn pads_code_blocks_with_wide_characters() {
+        let screen = render("```\n中文 x\nab\n```");
+        let lines = screen.lines();
+        assert_eq!(lines[2].trim_end(), "中文 x");
+        assert_eq!(visible_width(&lines[2]), 26);
+        assert_eq!(visible_width(&lines[3]), 26);
+
+        // The wide characters' right halves are empty, the padding is styled
+        // like the code up to the slide's right edge.
+        let buffer = screen.buffer();
+        let code = buffer.get(0, 2).unwrap().style;
+        assert!(code != Style::default());
+        assert_eq!(buffer.get(1, 2).unwrap().symbol, "");
+        assert_eq!(buffer.get(25, 2).unwrap().style, code);
+        assert_eq!(buffer.get(25, 3).unwrap().style, code);
+        assert_eq!(buffer.get(26, 3).unwrap().style, Style::default());
     }
 }
@@ -1,101 +1,770 @@
-use deck::{Deck, Slide};
-use get::get_string;
-use input::ImmediateInput;
+use asset::AssetStore;
+use deck::{Deck, Slide, SlideId};
+use error::Error;
+use export::export;
+use get::{Base, get_string, local_file};
+use guard::{self, Guard, Signal};
+use keymap::{Action, Keymap};
 use std::borrow::Cow;
-use std::io::{Result, Write, stdin};
-use termion::{color, cursor};
+use buffer::Style;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Result, stdin};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+use termion;
 use termion::event::Key;
 use termion::input::TermRead;
-use view::View;
+use theme::Theme;
+use view::{self, View};
+use watch::Watcher;
 
-fn _show_help(view: &mut View) -> Result<()> {
-    let help = r#"
-# mdp: a markdown presentation tool built in Rust
+/// Show the keys of `keymap` until any key is pressed. Returns whether to
+/// keep going. `changed` is set if the deck changed meanwhile.
+fn show_help(
+    keymap: &Keymap,
+    view: &mut View,
+    events: &Receiver<Event>,
+    changed: &mut bool,
+) -> Result<bool> {
+    let mut help = String::from("# mdp: a markdown presentation tool built in Rust\n\n");
+    for line in keymap.help() {
+        help.push_str("    ");
+        help.push_str(&line);
+        help.push('\n');
+    }
+    let slide = Slide::new((0, Cow::from(help)));
 
-- j, left, up => next slide
-- k, right, down, space => previous slide
-- a - go to first slide
-- $ - go to last slide
-- 1-9 - go to slide n
-- r - reload input file
-- q - exit
+    loop {
+        view.clear()?;
+        view.present(&slide)?;
+        view.info()?;
+        view.hide_cursor()?;
+        view.flush()?;
+        match events.recv() {
+            Ok(Event::Key(key)) => {
+                key?;
+                return Ok(true);
+            }
+            Ok(Event::Loaded(_)) => continue,
+            Ok(Event::Changed) => *changed = true,
+            Ok(Event::Signal(signal)) => {
+                if !on_signal(signal, view)? {
+                    return Ok(false);
+                }
+            }
+            Err(_) => return Ok(false),
+        }
+    }
+}
 
-"#;
+/// What the viewer waits for.
+enum Event {
+    Key(Result<Key>),
+    /// An image finished loading (or failed to).
+    Loaded(String),
+    /// The deck or one of its images changed.
+    Changed,
+    Signal(Signal),
+}
 
-    let slide = Slide::new((0, Cow::from(&help[..])));
-    view.present(&slide)?;
-    view.info()
+/// Send the keys read from `input` to `events`.
+fn read_keys<R: Read + Send + 'static>(input: R, events: Sender<Event>) {
+    thread::spawn(move || for key in input.keys() {
+        if events.send(Event::Key(key)).is_err() {
+            return;
+        }
+    });
 }
 
-pub fn play(path: &str) -> Result<()> {
-    let mut view = View::new()?;
-    let input = ImmediateInput::new(0);
-    input.set_immediate();
-    let mut slide_num = 0;
+/// The deck at `path`, where `-` is stdin.
+fn load(path: &str) -> ::std::result::Result<String, Error> {
+    if path == "-" {
+        let mut content = String::new();
+        stdin().read_to_string(&mut content).map_err(|e| {
+            Error::Fetch(String::from("stdin"), Box::new(Error::Io(e)))
+        })?;
+        Ok(content)
+    } else {
+        get_string(path)
+    }
+}
+
+/// Show the deck at `path` (a file, URL, or `-` for stdin). With `watch`, it's
+/// reloaded whenever it or one of its images changes. With `alternate_screen`,
+/// the terminal's contents are left alone and come back on quitting.
+pub fn play(path: &str, watch: bool, alternate_screen: bool) -> ::std::result::Result<(), Error> {
+    let keymap = Keymap::load()?;
+
+    // Keys come from the terminal, which isn't stdin if the deck is piped in.
+    let tty: Option<File> = if termion::is_tty(&stdin()) {
+        None
+    } else {
+        Some(termion::get_tty().map_err(Error::Terminal)?)
+    };
+
+    // stdin can only be read once, reloading shows the same deck.
+    let piped = if path == "-" { Some(load(path)?) } else { None };
+
+    let base = Base::of(path);
+
+    let (sender, events) = channel();
+    let assets = AssetStore::new();
+    {
+        let sender = Mutex::new(sender.clone());
+        assets.on_done(move |name| {
+            let _ = sender.lock().unwrap().send(Event::Loaded(String::from(name)));
+        });
+    }
+
+    // Only the first load has to work. After that, a deck that fails to load
+    // (like one that's being edited or checked out) leaves the last one that
+    // did on screen, with the error in the status line.
+    let mut content = match piped {
+        Some(ref content) => content.clone(),
+        None => load(path)?,
+    };
+    // Parsing loads the images of the first slide, which can take a while.
+    // Until the terminal is taken over, Ctrl+C still stops it.
+    Deck::new(&content, &base, &assets)?;
+
+    let _guard = {
+        let sender = Mutex::new(sender.clone());
+        let fd = tty.as_ref().map_or(0, |tty| tty.as_raw_fd());
+        Guard::new(fd, alternate_screen, move |signal| {
+            let _ = sender.lock().unwrap().send(Event::Signal(signal));
+        }).map_err(Error::Terminal)?
+    };
+    let mut view = View::new(alternate_screen).map_err(Error::Terminal)?;
+    match tty {
+        Some(ref tty) => read_keys(tty.try_clone()?, sender.clone()),
+        None => read_keys(stdin(), sender.clone()),
+    }
+    view.set_assets(&assets);
+
+    let watcher = if watch {
+        let sender = Mutex::new(sender.clone());
+        Some(Watcher::new(move || {
+            let _ = sender.lock().unwrap().send(Event::Changed);
+        })?)
+    } else {
+        None
+    };
+
+    // Reloaded content that's yet to be turned into a deck
+    let mut fresh: Option<String> = None;
+    let mut failure: Option<Error> = None;
+
+    // Identities of the slides before a reload, and the one that was shown
+    let mut previous: Option<(Vec<SlideId>, usize)> = None;
     loop {
-        let content = get_string(path)?;
-        let deck = Deck::new(&content)?;
-        let ret = show(deck, &mut view, slide_num)?;
-        match ret {
-            Some(num) => slide_num = num,
+        assets.invalidate();
+        let attempt = fresh.as_ref().map(|fresh| Deck::new(fresh, &base, &assets));
+        let deck = match attempt {
+            Some(Ok(deck)) => deck,
+            Some(Err(e)) => {
+                failure = Some(e);
+                Deck::new(&content, &base, &assets)?
+            }
+            None => Deck::new(&content, &base, &assets)?,
+        };
+        let loaded = failure.is_none();
+        assets.sweep();
+        if let Some(ref watcher) = watcher {
+            let mut files = assets.files();
+            files.extend(local_file(path));
+            if let Err(e) = watcher.watch(&files) {
+                warn!("can't watch for changes: {}", e);
+            }
+        }
+        view.invalidate();
+
+        let start = match previous {
+            Some((ref ids, current)) => deck.locate(ids, current),
+            None => 0,
+        };
+        let ids = deck.ids();
+        let status = match failure.take() {
+            Some(e) => Status::Error(e.to_string()),
+            None => Status::None,
+        };
+        let shown = show(deck, &mut view, &keymap, path, start, status, &events);
+        match shown.map_err(Error::Terminal)? {
+            Some(current) => previous = Some((ids, current)),
             None => break,
         }
+
+        if loaded {
+            if let Some(fresh) = fresh.take() {
+                content = fresh;
+            }
+        }
+        // stdin can't change, the same content is shown again
+        if piped.is_none() {
+            match load(path) {
+                Ok(text) => fresh = Some(text),
+                Err(e) => {
+                    fresh = None;
+                    failure = Some(e);
+                }
+            }
+        }
     }
     Ok(())
 }
 
-fn show(mut deck: Deck, view: &mut View, start: usize) -> Result<Option<usize>> {
-    let mut key_reader = stdin().keys();
-
+fn show(
+    mut deck: Deck,
+    view: &mut View,
+    keymap: &Keymap,
+    path: &str,
+    start: usize,
+    mut status: Status,
+    events: &Receiver<Event>,
+) -> Result<Option<usize>> {
     deck.goto(start);
-    view.clear()?;
-    view.present(deck.slide())?;
-    show_page_num(&deck, view)?;
-    view.hide_cursor()?;
-    view.flush()?;
+    redraw(&deck, view, &status)?;
 
-    'outer: loop {
-        while let Some(c) = key_reader.next() {
-            match c.unwrap() {
-                Key::Char('q') => {
-                    view.quit()?;
+    loop {
+        let event = match events.recv() {
+            Ok(event) => event,
+            // Nothing can read keys anymore
+            Err(_) => return Ok(None),
+        };
+        let key = match event {
+            Event::Key(key) => key?,
+            Event::Loaded(name) => {
+                debug!("{} loaded, redrawing", name);
+                redraw(&deck, view, &status)?;
+                continue;
+            }
+            Event::Changed => break,
+            Event::Signal(signal) => {
+                if !on_signal(signal, view)? {
                     return Ok(None);
                 }
-                Key::Char('r') => {
-                    view.update()?;
+                redraw(&deck, view, &status)?;
+                continue;
+            }
+        };
+
+        // The command line and typed numbers take keys first, messages go
+        // away with the next key.
+        let flow = match mem::replace(&mut status, Status::None) {
+            Status::Command(mut line) => {
+                match key {
+                    Key::Char('\n') => {
+                        let (flow, result) = command(&line, &mut deck, view, keymap, path, events)?;
+                        status = result;
+                        flow
+                    }
+                    Key::Esc => Flow::Stay,
+                    Key::Backspace if line.is_empty() => Flow::Stay,
+                    Key::Backspace => {
+                        line.pop();
+                        status = Status::Command(line);
+                        Flow::Stay
+                    }
+                    Key::Char(c) => {
+                        line.push(c);
+                        status = Status::Command(line);
+                        Flow::Stay
+                    }
+                    _ => {
+                        status = Status::Command(line);
+                        Flow::Stay
+                    }
+                }
+            }
+            Status::Number(mut digits) => {
+                match key {
+                    Key::Char(c) if c.is_ascii_digit() => {
+                        digits.push(c);
+                        status = Status::Number(digits);
+                        Flow::Stay
+                    }
+                    Key::Char('\n') => {
+                        status = goto(&mut deck, &digits);
+                        Flow::Stay
+                    }
+                    _ => on_key(key, &mut deck, view, keymap, &mut status, events)?,
+                }
+            }
+            _ => on_key(key, &mut deck, view, keymap, &mut status, events)?,
+        };
+
+        match flow {
+            Flow::Stay => redraw(&deck, view, &status)?,
+            Flow::Reload => break,
+            Flow::Quit => return Ok(None),
+        }
+    }
+    Ok(Some(deck.current_num()))
+}
+
+/// What the viewer does after an action.
+enum Flow {
+    Stay,
+    Reload,
+    Quit,
+}
+
+/// What's shown in the status line instead of the deck's metadata.
+enum Status {
+    None,
+    /// Digits typed so far, the slide to go to on Enter.
+    Number(String),
+    /// The command line being edited, without the `:`.
+    Command(String),
+    /// What the last command did.
+    Message(String),
+    /// Why the last command failed.
+    Error(String),
+}
+
+/// Act on `key` according to `keymap`.
+fn on_key(
+    key: Key,
+    deck: &mut Deck,
+    view: &mut View,
+    keymap: &Keymap,
+    status: &mut Status,
+    events: &Receiver<Event>,
+) -> Result<Flow> {
+    let action = match keymap.get(&key) {
+        Some(action) => action,
+        None => return Ok(Flow::Stay),
+    };
+    match (action, key) {
+        (Action::Command, _) => *status = Status::Command(String::new()),
+        // More digits and Enter go to slides past 9
+        (Action::Goto(_), Key::Char(c)) if c.is_ascii_digit() => {
+            *status = Status::Number(c.to_string());
+        }
+        _ => {}
+    }
+    act(action, deck, view, keymap, events)
+}
+
+fn act(
+    action: Action,
+    deck: &mut Deck,
+    view: &mut View,
+    keymap: &Keymap,
+    events: &Receiver<Event>,
+) -> Result<Flow> {
+    match action {
+        Action::Quit => {
+            view.quit()?;
+            return Ok(Flow::Quit);
+        }
+        Action::Reload => return Ok(Flow::Reload),
+        Action::Next => deck.next(),
+        Action::Previous => deck.previous(),
+        Action::First => deck.goto(0),
+        Action::Last => {
+            let last = deck.total_num() - 1;
+            deck.goto(last);
+        }
+        Action::Goto(n) => deck.goto(n),
+        Action::Toc => {
+            let current = deck.current_num();
+            let mut changed = false;
+            match show_toc(deck, view, current, events, &mut changed)? {
+                Toc::Selected(n) => deck.goto(n),
+                Toc::Closed => {}
+                Toc::Quit => return Ok(Flow::Quit),
+            }
+            if changed {
+                return Ok(Flow::Reload);
+            }
+        }
+        Action::Command => {}
+        Action::Help => {
+            let mut changed = false;
+            if !show_help(keymap, view, events, &mut changed)? {
+                return Ok(Flow::Quit);
+            }
+            if changed {
+                return Ok(Flow::Reload);
+            }
+        }
+    }
+    Ok(Flow::Stay)
+}
+
+/// Go to the slide numbered `number` (from 1).
+fn goto(deck: &mut Deck, number: &str) -> Status {
+    match number.parse::<usize>() {
+        Ok(n) if n >= 1 && n <= deck.total_num() => {
+            deck.goto(n - 1);
+            Status::None
+        }
+        _ => Status::Error(format!("no slide {}, there are {}", number, deck.total_num())),
+    }
+}
+
+/// Run the command `line`: a slide number, an action of the keymap (like
+/// `first` or `reload`), `theme NAME` or `export [FILE]`.
+fn command(
+    line: &str,
+    deck: &mut Deck,
+    view: &mut View,
+    keymap: &Keymap,
+    path: &str,
+    events: &Receiver<Event>,
+) -> Result<(Flow, Status)> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok((Flow::Stay, Status::None));
+    }
+    if line.chars().all(|c| c.is_ascii_digit()) {
+        return Ok((Flow::Stay, goto(deck, line)));
+    }
+
+    let mut words = line.splitn(2, char::is_whitespace);
+    let name = words.next().unwrap_or("");
+    let arg = words.next().unwrap_or("").trim();
+    let status = match name {
+        "theme" => {
+            match Theme::named(arg) {
+                Some(theme) => {
+                    view.set_theme(theme);
+                    Status::None
                 }
-                Key::Char('l') => {
-                    break 'outer;
+                None => Status::Error(format!("unknown theme `{}`, there's dark and light", arg)),
+            }
+        }
+        "export" => {
+            let file = if arg.is_empty() {
+                export_file(path)
+            } else {
+                PathBuf::from(arg)
+            };
+            let (width, height) = view.term_size();
+            match export(deck, width, height, &file) {
+                Ok(()) => {
+                    let slides = deck.total_num();
+                    Status::Message(format!("exported {} slides to {}", slides, file.display()))
                 }
-                Key::Right | Key::Down | Key::Char('j') | Key::Char(' ') => {
-                    deck.next();
+                Err(Error::Save(_, ref e)) if e.kind() == ErrorKind::AlreadyExists => {
+                    Status::Error(format!("{} exists, give another file name", file.display()))
                 }
-                Key::Left | Key::Up | Key::Char('k') => {
-                    deck.previous();
+                Err(e) => Status::Error(e.to_string()),
+            }
+        }
+        _ => {
+            match Action::parse(line) {
+                Some(action) => return Ok((act(action, deck, view, keymap, events)?, Status::None)),
+                None => Status::Error(format!("unknown command `{}`", line)),
+            }
+        }
+    };
+    Ok((Flow::Stay, status))
+}
+
+/// Where `:export` writes to by default: next to the deck if it's a file.
+fn export_file(path: &str) -> PathBuf {
+    match local_file(path) {
+        // Don't overwrite a deck that's a .txt file itself
+        Some(ref file) if file.extension().is_some_and(|ext| ext == "txt") => {
+            file.with_extension("slides.txt")
+        }
+        Some(file) => file.with_extension("txt"),
+        None => PathBuf::from("slides.txt"),
+    }
+}
+
+/// How the table of contents was left.
+enum Toc {
+    /// A slide was selected with Enter.
+    Selected(usize),
+    /// Closed with ESC or q.
+    Closed,
+    /// The viewer has to quit.
+    Quit,
+}
+
+/// Show the table of contents with a cursor that starts at slide `start`.
+/// `changed` is set if the deck changed meanwhile, to reload it once the
+/// table is closed.
+fn show_toc(
+    deck: &Deck,
+    view: &mut View,
+    start: usize,
+    events: &Receiver<Event>,
+    changed: &mut bool,
+) -> Result<Toc> {
+    let toc = deck.toc();
+    let mut selected = start;
+    loop {
+        draw_toc(&toc, view, selected)?;
+        let key = match events.recv() {
+            Ok(Event::Key(key)) => key?,
+            Ok(Event::Loaded(_)) => continue,
+            Ok(Event::Changed) => {
+                *changed = true;
+                continue;
+            }
+            Ok(Event::Signal(signal)) => {
+                if !on_signal(signal, view)? {
+                    return Ok(Toc::Quit);
                 }
-                _ => {}
+                continue;
             }
+            Err(_) => return Ok(Toc::Quit),
+        };
+        match key {
+            Key::Esc | Key::Char('q') => return Ok(Toc::Closed),
+            Key::Char('\n') => return Ok(Toc::Selected(selected)),
+            Key::Down | Key::Char('j') if selected + 1 < toc.len() => selected += 1,
+            Key::Up | Key::Char('k') => selected = selected.saturating_sub(1),
+            _ => {}
+        }
+    }
+}
 
-            view.clear()?;
-            view.present(deck.slide())?;
-            show_page_num(&deck, view)?;
-            view.hide_cursor()?;
-            view.flush()?;
+/// Act on `signal`, returning whether to keep going. The screen needs to be
+/// redrawn afterwards.
+fn on_signal(signal: Signal, view: &mut View) -> Result<bool> {
+    match signal {
+        Signal::Quit => {
+            view.quit()?;
+            return Ok(false);
         }
+        Signal::Suspend => guard::suspend()?,
+        Signal::Continue => guard::resume(),
+        Signal::Resize => {}
     }
-    Ok(Some(deck.current_num()))
+    // The terminal may also have been resized while we were stopped
+    view.update()?;
+    Ok(true)
 }
 
+fn draw_toc(toc: &[Option<&str>], view: &mut View, selected: usize) -> Result<()> {
+    let (x, y) = view.left_top();
+    let rows = view.height().saturating_sub(2) as usize;
+    let first = if selected < rows { 0 } else { selected + 1 - rows };
+
+    view.clear()?;
+    let header = view.theme().header;
+    view.print_at(x, y, "Table of contents", header)?;
+
+    for (i, title) in toc.iter().enumerate().skip(first).take(rows) {
+        let row = y + 2 + (i - first) as u16;
+        let style = Style {
+            invert: i == selected,
+            ..Style::default()
+        };
+        let title = title.unwrap_or("(untitled)");
+        let line = format!("{:>3}. {}", i + 1, title);
+        let line = view::truncate(&line, view.width() as usize);
+        view.print_at(x, row, line, style)?;
+    }
+
+    view.hide_cursor()?;
+    view.flush()
+}
 
-fn show_page_num<'a>(deck: &'a Deck, view: &mut View) -> Result<()> {
+fn redraw(deck: &Deck, view: &mut View, status: &Status) -> Result<()> {
+    view.clear()?;
+    // A slide that can't be laid out is shown as far as it goes
+    let failed = view.present(&deck.visible())
+        .err()
+        .map(|e| Status::Error(format!("can't show this slide: {}", e)));
+    let status = failed.as_ref().unwrap_or(status);
+    let page_num = show_page_num(deck, view)?;
+    match *status {
+        Status::None => show_meta(deck, view, page_num)?,
+        _ => show_status(status, view, page_num)?,
+    }
+    view.hide_cursor()?;
+    view.flush()
+}
+
+/// Draw `status` at the bottom left, leaving room for the page counter.
+fn show_status(status: &Status, view: &mut View, page_num: u16) -> Result<()> {
+    let (s, style) = match *status {
+        Status::None => return Ok(()),
+        Status::Number(ref digits) => (digits.clone(), Style::default()),
+        Status::Command(ref line) => (format!(":{}", line), Style::default()),
+        Status::Message(ref message) => (message.clone(), Style::default()),
+        Status::Error(ref error) => (error.clone(), view.theme().error),
+    };
+
+    // Keep the end of the command line in sight, with room for the cursor
+    let room = view.width().saturating_sub(page_num + 3) as usize;
+    let s = match *status {
+        Status::Command(_) if view::visible_width(&s) > room => {
+            let skip = s.chars().count().saturating_sub(room);
+            s.chars().skip(skip).collect()
+        }
+        _ => String::from(view::truncate(&s, room)),
+    };
+    let (x, y) = view.left_bottom();
+    view.print_at(x, y, &s, style)?;
+    if let Status::Command(_) = *status {
+        let cursor = Style {
+            invert: true,
+            ..Style::default()
+        };
+        view.print_at(x + view::visible_width(&s) as u16, y, " ", cursor)?;
+    }
+    Ok(())
+}
+
+/// Draw the page counter at the bottom right and return its width.
+fn show_page_num(deck: &Deck, view: &mut View) -> Result<u16> {
     use std::fmt::Write;
     let mut s = String::new();
     write!(&mut s, "{} / {}", deck.current_num() + 1, deck.total_num()).unwrap();
-    let (mut x, y) = view.right_bottom();
-    x -=  s.len() as u16;
-    write!(view, "{}", cursor::Goto(x, y))?;
-    write!(view, "{}", color::Fg(color::LightRed))?;
-    write!(view, "{}", s)?;
-    write!(view, "{}", color::Fg(color::Reset))?;
+    let steps = deck.slide().steps();
+    if steps > 1 {
+        write!(&mut s, " ({}/{})", deck.current_step() + 1, steps).unwrap();
+    }
+    let width = view::visible_width(&s) as u16;
+    let (x, y) = view.right_bottom();
+    let x = x.saturating_sub(width);
+    let style = view.theme().page_num;
+    view.print_at(x, y, &s, style)?;
+    Ok(width)
+}
+
+/// Draw title, author and date from the deck's metadata at the bottom left,
+/// leaving room for the page counter.
+fn show_meta(deck: &Deck, view: &mut View, page_num: u16) -> Result<()> {
+    let meta = deck.meta();
+    let fields = [&meta.title, &meta.author, &meta.date];
+    let s = fields
+        .iter()
+        .filter_map(|field| field.as_ref())
+        .map(|field| &field[..])
+        .collect::<Vec<_>>()
+        .join(" | ");
+    if s.is_empty() {
+        return Ok(());
+    }
+
+    let room = view.width().saturating_sub(page_num + 2) as usize;
+    let s = view::truncate(&s, room);
+    let (x, y) = view.left_bottom();
+    let style = view.theme().dim;
+    view.print_at(x, y, s, style)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asset::AssetStore;
+    use backend::Headless;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    const DECK: &str = "# One\n\n---\n\n# Two\n\n---\n\n# Three\n";
+
+    fn deck(content: &str) -> Deck<'_> {
+        Deck::new(content, &Base::of("deck.md"), &AssetStore::new()).unwrap()
+    }
+
+    fn view() -> View {
+        View::with_backend(Box::new(Headless::new(40, 12))).unwrap()
+    }
+
+    /// Run `line` as typed after `:` in a viewer of the deck at `path`.
+    fn run(line: &str, deck: &mut Deck, view: &mut View, path: &str) -> Status {
+        let (_events, receiver) = channel();
+        match command(line, deck, view, &Keymap::default(), path, &receiver).unwrap() {
+            (Flow::Stay, status) => status,
+            _ => panic!("`{}` should stay in the viewer", line),
+        }
+    }
+
+    fn error(status: Status) -> String {
+        match status {
+            Status::Error(why) => why,
+            _ => panic!("not an error"),
+        }
+    }
+
+    #[test]
+    fn goto_slide_numbers() {
+        let mut deck = deck(DECK);
+        match goto(&mut deck, "2") {
+            Status::None => assert_eq!(deck.current_num(), 1),
+            _ => panic!("slide 2 exists"),
+        }
+        assert_eq!(error(goto(&mut deck, "4")), "no slide 4, there are 3");
+        assert_eq!(error(goto(&mut deck, "0")), "no slide 0, there are 3");
+        assert_eq!(deck.current_num(), 1);
+    }
+
+    #[test]
+    fn commands() {
+        let mut deck = deck(DECK);
+        let mut view = view();
+        run("3", &mut deck, &mut view, "deck.md");
+        assert_eq!(deck.current_num(), 2);
+        run(" first ", &mut deck, &mut view, "deck.md");
+        assert_eq!(deck.current_num(), 0);
+        run("last", &mut deck, &mut view, "deck.md");
+        assert_eq!(deck.current_num(), 2);
+
+        run("theme light", &mut deck, &mut view, "deck.md");
+        assert_eq!(*view.theme(), Theme::light());
+        let why = error(run("theme blue", &mut deck, &mut view, "deck.md"));
+        assert_eq!(why, "unknown theme `blue`, there's dark and light");
+        let why = error(run("jump 3", &mut deck, &mut view, "deck.md"));
+        assert_eq!(why, "unknown command `jump 3`");
+    }
+
+    #[test]
+    fn tiny_terminals() {
+        let content = "# Title\n\n-> centered <-\n\n- a\n- b\n\n```\ncode\n```\n\n> quote";
+        let deck = deck(content);
+        let toc = deck.toc();
+        for &(width, height) in &[(0, 0), (1, 1), (3, 3), (6, 2), (2, 12)] {
+            let mut view = View::with_backend(Box::new(Headless::new(width, height))).unwrap();
+            redraw(&deck, &mut view, &Status::None).unwrap();
+            redraw(&deck, &mut view, &Status::Error(String::from("failed"))).unwrap();
+            draw_toc(&toc, &mut view, 0).unwrap();
+        }
+    }
+
+    #[test]
+    fn export_next_to_the_deck() {
+        assert_eq!(export_file("talk/deck.md"), PathBuf::from("talk/deck.txt"));
+        assert_eq!(export_file("notes.txt"), PathBuf::from("notes.slides.txt"));
+        assert_eq!(export_file("-"), PathBuf::from("slides.txt"));
+        assert_eq!(export_file("https://example.com/deck.md"), PathBuf::from("slides.txt"));
+    }
+
+    #[test]
+    fn export() {
+        let dir = env::temp_dir().join(format!("mdp-export-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let deck_path = dir.join("deck.md");
+        let path = deck_path.to_str().unwrap();
+
+        let mut deck = deck(DECK);
+        let mut view = view();
+        match run("export", &mut deck, &mut view, path) {
+            Status::Message(message) => {
+                let file = dir.join("deck.txt");
+                assert_eq!(message, format!("exported 3 slides to {}", file.display()));
+                let mut text = String::new();
+                File::open(&file).unwrap().read_to_string(&mut text).unwrap();
+                assert!(text.contains("One") && text.contains("Three"));
+            }
+            _ => panic!("exporting failed"),
+        }
+        let why = error(run("export", &mut deck, &mut view, path));
+        assert!(why.ends_with("deck.txt exists, give another file name"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
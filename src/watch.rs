@@ -0,0 +1,280 @@
+//! Watch the files of a deck for changes
+//!
+//! On Linux this uses inotify on the directories of the files, so that files
+//! replaced by editors (written to a temporary file and renamed) are still
+//! noticed. Elsewhere the modification times are polled.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::Result;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Group `files` by their directory.
+fn by_dir(files: &[PathBuf]) -> HashMap<PathBuf, Vec<OsString>> {
+    let mut dirs: HashMap<PathBuf, Vec<OsString>> = HashMap::new();
+    for file in files {
+        let dir = match file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        if let Some(name) = file.file_name() {
+            dirs.entry(dir).or_default().push(
+                name.to_os_string(),
+            );
+        }
+    }
+    dirs
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use libc;
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr, OsString};
+    use std::io::{Error, ErrorKind, Result};
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::RawFd;
+    use std::path::PathBuf;
+    use std::ptr;
+    use std::sync::Mutex;
+
+    /// Milliseconds to wait for more changes before reporting them, as
+    /// editors often write a file in several steps.
+    const SETTLE: i32 = 100;
+
+    /// Watched directories by watch descriptor, with the names of the files
+    /// in them.
+    pub type Dirs = HashMap<i32, (PathBuf, Vec<OsString>)>;
+
+    pub fn init() -> Result<RawFd> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(fd)
+    }
+
+    pub fn close(fd: RawFd) {
+        unsafe { libc::close(fd) };
+    }
+
+    /// Watch the directories in `wanted` instead of those in `dirs`. A
+    /// directory that can't be watched (e.g. one that doesn't exist yet) is
+    /// skipped, the others are still watched.
+    pub fn watch(fd: RawFd, dirs: &mut Dirs, wanted: HashMap<PathBuf, Vec<OsString>>) -> Result<()> {
+        let stale: Vec<i32> = dirs.iter()
+            .filter(|&(_, (dir, _))| !wanted.contains_key(dir))
+            .map(|(&wd, _)| wd)
+            .collect();
+        for wd in stale {
+            unsafe { libc::inotify_rm_watch(fd, wd) };
+            dirs.remove(&wd);
+        }
+
+        let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE | libc::IN_DELETE;
+        for (dir, names) in wanted {
+            let path = match CString::new(dir.as_os_str().as_bytes()) {
+                Ok(path) => path,
+                Err(e) => {
+                    warn!("can't watch {}: {}", dir.display(), e);
+                    continue;
+                }
+            };
+            let wd = unsafe { libc::inotify_add_watch(fd, path.as_ptr(), mask) };
+            if wd < 0 {
+                warn!("can't watch {}: {}", dir.display(), Error::last_os_error());
+                continue;
+            }
+            dirs.insert(wd, (dir, names));
+        }
+        Ok(())
+    }
+
+    /// Call `f` after watched files changed, until `fd` is closed.
+    pub fn run<F: Fn()>(fd: RawFd, dirs: &Mutex<Dirs>, f: F) {
+        let mut buf = [0u8; 4096];
+        loop {
+            match read(fd, &mut buf, dirs) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => return,
+            }
+
+            // Wait for the writes to settle
+            loop {
+                let mut pollfd = libc::pollfd {
+                    fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                if unsafe { libc::poll(&mut pollfd, 1, SETTLE) } <= 0 {
+                    break;
+                }
+                if read(fd, &mut buf, dirs).is_err() {
+                    break;
+                }
+            }
+            f();
+        }
+    }
+
+    /// Read a batch of events, returning whether any was about a watched
+    /// file.
+    fn read(fd: RawFd, buf: &mut [u8], dirs: &Mutex<Dirs>) -> Result<bool> {
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n <= 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let dirs = dirs.lock().unwrap();
+        let header = mem::size_of::<libc::inotify_event>();
+        let mut changed = false;
+        let mut i = 0;
+        while i + header <= n as usize {
+            let event: libc::inotify_event =
+                unsafe { ptr::read_unaligned(buf[i..].as_ptr() as *const libc::inotify_event) };
+            let end = (i + header + event.len as usize).min(n as usize);
+            let name = &buf[i + header..end];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            if let Some((_, names)) = dirs.get(&event.wd) {
+                if names.iter().any(|n| n == OsStr::from_bytes(name)) {
+                    changed = true;
+                }
+            }
+            i = end;
+        }
+        Ok(changed)
+    }
+}
+
+/// Calls a function when any of the watched files changes.
+#[cfg(target_os = "linux")]
+pub struct Watcher {
+    fd: ::std::os::unix::io::RawFd,
+    dirs: Arc<Mutex<imp::Dirs>>,
+}
+
+#[cfg(target_os = "linux")]
+impl Watcher {
+    /// Start watching (nothing yet), calling `f` from another thread after
+    /// files changed.
+    pub fn new<F: Fn() + Send + 'static>(f: F) -> Result<Watcher> {
+        let fd = imp::init()?;
+        let dirs = Arc::new(Mutex::new(HashMap::new()));
+        {
+            let dirs = dirs.clone();
+            thread::spawn(move || imp::run(fd, &dirs, f));
+        }
+        Ok(Watcher { fd, dirs })
+    }
+
+    /// Watch `files` instead of the ones watched before.
+    pub fn watch(&self, files: &[PathBuf]) -> Result<()> {
+        let mut dirs = self.dirs.lock().unwrap();
+        imp::watch(self.fd, &mut dirs, by_dir(files))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        imp::close(self.fd);
+    }
+}
+
+/// Calls a function when any of the watched files changes.
+#[cfg(not(target_os = "linux"))]
+pub struct Watcher {
+    files: Arc<Mutex<HashMap<PathBuf, Option<::std::time::SystemTime>>>>,
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    /// Start watching (nothing yet), calling `f` from another thread after
+    /// files changed.
+    pub fn new<F: Fn() + Send + 'static>(f: F) -> Result<Watcher> {
+        use std::sync::Weak;
+        use std::time::Duration;
+
+        let files = Arc::new(Mutex::new(HashMap::new()));
+        let weak: Weak<Mutex<HashMap<_, _>>> = Arc::downgrade(&files);
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_millis(500));
+            let files = match weak.upgrade() {
+                Some(files) => files,
+                None => return,
+            };
+            let mut changed = false;
+            for (file, modified) in files.lock().unwrap().iter_mut() {
+                let now = modified_time(file);
+                if now != *modified {
+                    *modified = now;
+                    changed = true;
+                }
+            }
+            if changed {
+                f();
+            }
+        });
+        Ok(Watcher { files: files })
+    }
+
+    /// Watch `files` instead of the ones watched before.
+    pub fn watch(&self, files: &[PathBuf]) -> Result<()> {
+        let mut watched = self.files.lock().unwrap();
+        watched.clear();
+        for (dir, names) in by_dir(files) {
+            for name in names {
+                let file = dir.join(name);
+                let modified = modified_time(&file);
+                watched.insert(file, modified);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn modified_time(file: &PathBuf) -> Option<::std::time::SystemTime> {
+    ::std::fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::process;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn missing_dirs_are_skipped() {
+        let dir = env::temp_dir().join(format!("mdp-watch-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let deck = dir.join("deck.md");
+        File::create(&deck).unwrap();
+
+        let (changed, changes) = channel();
+        let watcher = Watcher::new(move || {
+            let _ = changed.send(());
+        }).unwrap();
+        // Many missing directories, so that some come before the deck's
+        let mut files: Vec<PathBuf> = (0..8)
+            .map(|i| dir.join(format!("missing{}", i)).join("image.png"))
+            .collect();
+        files.push(deck.clone());
+        watcher.watch(&files).unwrap();
+
+        File::create(&deck).unwrap().write_all(b"# Slide\n").unwrap();
+        assert!(changes.recv_timeout(Duration::from_secs(5)).is_ok());
+
+        drop(watcher);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}